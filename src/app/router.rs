@@ -1,18 +1,35 @@
 use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
 use axum::Extension;
-use axum::extract::Path;
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
-use axum::routing::get;
-use opentelemetry::metrics::MeterProvider;
+use axum::routing::{get, post};
+use opentelemetry::metrics::{Meter, MeterProvider};
 use prometheus::Registry;
-use crate::config::{Config, SourceKind};
+use crate::config::{Config, Source, SourceKind};
 use crate::error::Error;
-use crate::handler::Dependencies;
+use crate::handler::{Admin, Dependencies, Negotiator};
 use crate::processor::chainer::ChainProcessor;
 use crate::{handler, storage};
 use crate::storage::webfolder::WebFolderGetter;
+use crate::storage::s3::S3Getter;
+use crate::storage::filesystem::FileSystemGetter;
+use crate::storage::routing::{Route, RoutingGetter};
+use crate::storage::fallback::FallbackGetter;
+use crate::storage::retry::RetryGetter;
+use crate::storage::breaker::CircuitBreakerGetter;
+use crate::storage::coalesce::CoalescingGetter;
+use crate::storage::stale::StaleGetter;
+use crate::coalesce::Coalescer;
+use crate::pool::BlockingPool;
+use crate::cache::Cache;
+use crate::cache::memory::MemoryCache;
+use crate::cache::noop::NoCache;
+use crate::cache::disk::DiskCache;
+use crate::cache::tiered::TieredCache;
+use crate::cache::redis::RedisCache;
+use crate::cdn::WebhookPurger;
+use crate::storage::cache::CachingGetter;
 use crate::prelude::Result;
 
 pub struct Router {
@@ -31,40 +48,178 @@ impl DerefMut for Router {
 
 impl Router {
     pub fn new(cfg: &Config, reg: Registry) -> Result<Self> {
-        let storage: Arc<dyn storage::Getter + Send + Sync> = match cfg.source.kind {
-            SourceKind::WebFolder => {
-                let web_folder = cfg.source.web_folder.clone()
-                    .ok_or(Error::Generic("WebFolder source is not configured".into()))?;
-                let prefix = cfg.source.path_prefix.clone();
-                let prefix = prefix.map(|s| Box::leak(s.into_boxed_str()) as &str);
-
-                println!("WebFolder: {}", web_folder.base_url.deref().to_string());
-
-                Arc::new(
-                    WebFolderGetter::new(web_folder.base_url, prefix)
-                )
-            }
-        };
-
         let meter = Arc::new(
             opentelemetry::global::meter_provider()
                 .meter("darkroom-rs")
         );
+
+        let disk: Option<Arc<DiskCache>> = match &cfg.cache.disk {
+            Some(disk) => Some(Arc::new(DiskCache::new(disk.path.clone(), disk.max_bytes, &meter)?)),
+            None => None,
+        };
+
+        let routes = cfg.sources()
+            .map(|source| {
+                let mut getter = Self::getter(source, cfg.limits.max_source_bytes, &meter)?;
+                if let Some(disk) = &disk {
+                    let cache_time = source.cache_duration.unwrap_or(cfg.handler.response.cache_duration);
+                    getter = Arc::new(CachingGetter::new(
+                        getter,
+                        disk.clone(),
+                        cache_time,
+                        cfg.handler.response.upstream_max_age,
+                    ));
+                }
+
+                Route::new(source.pattern.as_deref(), getter, source.cache_duration)
+                    .map_err(|e| Error::Generic(format!("invalid source pattern: {}", e)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if routes.is_empty() {
+            return Err(Error::Generic("no source is configured".into()));
+        }
+
+        let storage = Arc::new(
+            CoalescingGetter::new(Arc::new(RoutingGetter::new(routes)), &meter)
+        );
+
         let deps = Arc::new(Dependencies {
             registry: Arc::new(reg),
             storage,
-            processor: Arc::new(ChainProcessor::new(meter)),
+            processor: Arc::new(ChainProcessor::new(meter.clone())),
+            coalescer: Arc::new(Coalescer::new("processor", &meter)),
+            pool: Arc::new(BlockingPool::new(&cfg.pool, &meter)),
+            limits: cfg.limits,
+            output: cfg.handler.output.clone(),
+            negotiator: Arc::new(Negotiator::new(cfg.handler.output.auto_formats.clone(), &meter)),
+            cache: Self::cache(cfg, disk, &meter)?,
             cache_time: cfg.handler.response.cache_duration,
+            upstream_max_age: cfg.handler.response.upstream_max_age,
+            stale_while_revalidate: cfg.handler.response.stale_while_revalidate,
+            stale_if_error: cfg.handler.response.stale_if_error,
         });
 
-        Ok(Self { inner: Self::build_router(deps) })
+        let admin = match &cfg.admin {
+            Some(admin) => Some(Arc::new(Admin {
+                token: admin.token.clone(),
+                purger: Arc::new(
+                    WebhookPurger::new(admin.purge.clone()).map_err(|e| Error::Generic(e.to_string()))?
+                ),
+                cache: deps.cache.clone(),
+                storage: deps.storage.clone(),
+            })),
+            None => None,
+        };
+
+        Ok(Self { inner: Self::build_router(deps, admin) })
     }
 
-    fn build_router(deps: Arc<Dependencies>) -> axum::Router {
-        axum::Router::new()
+    fn cache(cfg: &Config, disk: Option<Arc<DiskCache>>, meter: &Meter) -> Result<Arc<dyn Cache + Send + Sync>> {
+        let mut tiers: Vec<Arc<dyn Cache + Send + Sync>> = vec![];
+        if let Some(memory) = &cfg.cache.memory {
+            tiers.push(Arc::new(MemoryCache::new(memory.max_bytes, meter)));
+        }
+        if let Some(disk) = disk {
+            tiers.push(disk);
+        }
+        if let Some(redis) = &cfg.cache.redis {
+            let cache = RedisCache::new(redis.clone(), meter).map_err(|err| Error::Generic(err.to_string()))?;
+            tiers.push(Arc::new(cache));
+        }
+
+        Ok(match tiers.len() {
+            0 => Arc::new(NoCache),
+            1 => tiers.remove(0),
+            _ => Arc::new(TieredCache::new(tiers)),
+        })
+    }
+
+    /// Builds the getter of a source, whose `get` refuses objects over `max_bytes`.
+    fn getter(
+        source: &Source,
+        max_bytes: Option<u64>,
+        meter: &Arc<Meter>,
+    ) -> Result<Arc<dyn storage::Getter + Send + Sync>> {
+        let prefix = source.path_prefix.clone();
+        let prefix = prefix.map(|s| Box::leak(s.into_boxed_str()) as &str);
+
+        let getter: Arc<dyn storage::Getter + Send + Sync> = match source.kind {
+            SourceKind::WebFolder => {
+                let web_folder = source.web_folder.clone()
+                    .ok_or(Error::Generic("WebFolder source is not configured".into()))?;
+
+                println!("WebFolder: {}", web_folder.base_url.deref());
+
+                Arc::new(
+                    WebFolderGetter::new(web_folder.base_url, prefix, &source.network.config, max_bytes)?
+                )
+            }
+            SourceKind::S3 => {
+                let s3 = source.s3.clone()
+                    .ok_or(Error::Generic("S3 source is not configured".into()))?;
+
+                println!("S3: {}", s3.bucket);
+
+                Arc::new(
+                    S3Getter::new(s3, prefix, &source.network.config, max_bytes)?
+                )
+            }
+            SourceKind::FileSystem => {
+                let file_system = source.file_system.clone()
+                    .ok_or(Error::Generic("FileSystem source is not configured".into()))?;
+
+                println!("FileSystem: {}", file_system.root.display());
+
+                Arc::new(
+                    FileSystemGetter::new(file_system.root, prefix, max_bytes)?
+                )
+            }
+            SourceKind::Fallback => {
+                let backends = source.fallback.as_ref()
+                    .filter(|sources| !sources.is_empty())
+                    .ok_or(Error::Generic("Fallback source is not configured".into()))?
+                    .iter()
+                    .map(|s| Ok((s.name(), Self::getter(s, max_bytes, meter)?)))
+                    .collect::<Result<Vec<_>>>()?;
+
+                Arc::new(
+                    FallbackGetter::new(backends, meter.clone())
+                )
+            }
+        };
+
+        let getter: Arc<dyn storage::Getter + Send + Sync> = match source.circuit_breaker.clone() {
+            Some(circuit_breaker) => Arc::new(
+                CircuitBreakerGetter::new(source.name(), getter, circuit_breaker, meter.clone())
+            ),
+            None => getter,
+        };
+
+        let getter: Arc<dyn storage::Getter + Send + Sync> = match source.retry.clone() {
+            Some(retry) => Arc::new(
+                RetryGetter::new(source.name(), getter, retry, source.network.config.timeout, meter.clone())
+            ),
+            None => getter,
+        };
+
+        Ok(match source.stale_if_error.clone() {
+            Some(stale_if_error) => Arc::new(
+                StaleGetter::new(source.name(), getter, stale_if_error, meter.clone())
+            ),
+            None => getter,
+        })
+    }
+
+    fn build_router(deps: Arc<Dependencies>, admin: Option<Arc<Admin>>) -> axum::Router {
+        let mut router = axum::Router::new()
             .route("/*path", get(handler::image))
-            .route("/metrics", get(metrics_handler))
-            .layer(Extension(deps))
+            .route("/metrics", get(metrics_handler));
+
+        if let Some(admin) = admin {
+            router = router.route("/_admin/purge", post(handler::purge).layer(Extension(admin)));
+        }
+        router.layer(Extension(deps))
     }
 }
 
@@ -0,0 +1,113 @@
+//! Layout of entries stored outside the process: the length of the header as a little-endian
+//! `u32`, the header as JSON, then the content. The header keeps the key of the entry, so that
+//! entries can be purged by key where they are stored under its hash.
+
+use std::io::Read;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use axum::body::Bytes;
+use serde::{Deserialize, Serialize};
+use crate::cache::Entry;
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    #[serde(default)]
+    key: String,
+    content_type: Option<String>,
+    etag: Option<String>,
+    last_modified: Option<u64>,
+    backend: Option<String>,
+    cache_control: Option<u64>,
+    cache_time: u64,
+    expires: u64,
+}
+
+pub fn encode(key: &str, entry: &Entry, expires: SystemTime) -> Vec<u8> {
+    let header = serde_json::to_vec(&Header {
+        key: key.to_string(),
+        content_type: entry.content_type.clone(),
+        etag: entry.etag.clone(),
+        last_modified: entry.last_modified.map(millis),
+        backend: entry.backend.clone(),
+        cache_control: entry.cache_control.map(|d| d.as_millis() as u64),
+        cache_time: entry.cache_time.as_millis() as u64,
+        expires: millis(expires),
+    }).expect("cache entry header must serialize");
+
+    let mut bytes = Vec::with_capacity(4 + header.len() + entry.content.len());
+    bytes.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&header);
+    bytes.extend_from_slice(&entry.content);
+    bytes
+}
+
+/// Returns the entry along with when it expires, or `None` when the bytes are not an entry.
+pub fn decode(bytes: Bytes) -> Option<(Entry, SystemTime)> {
+    let len = u32::from_le_bytes(bytes.get(..4)?.try_into().ok()?) as usize;
+    let header: Header = serde_json::from_slice(bytes.get(4..4 + len)?).ok()?;
+
+    let entry = Entry {
+        content: bytes.slice(4 + len..),
+        content_type: header.content_type,
+        etag: header.etag,
+        last_modified: header.last_modified.map(time),
+        backend: header.backend,
+        cache_control: header.cache_control.map(Duration::from_millis),
+        cache_time: Duration::from_millis(header.cache_time),
+    };
+    Some((entry, time(header.expires)))
+}
+
+/// Reads only as much as needed to tell the key of the entry and when it expires. `size` is
+/// the length of the whole entry, which the length of the header must fit in.
+pub fn peek(mut reader: impl Read, size: u64) -> Option<(String, SystemTime)> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len).ok()?;
+
+    let len = u32::from_le_bytes(len);
+    if 4 + len as u64 > size {
+        return None;
+    }
+    let mut header = vec![0; len as usize];
+    reader.read_exact(&mut header).ok()?;
+
+    serde_json::from_slice::<Header>(&header).ok().map(|h| (h.key, time(h.expires)))
+}
+
+fn millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+fn time(millis: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let entry = Entry {
+            content: Bytes::from_static(&[1, 2, 3]),
+            content_type: Some("image/png".to_string()),
+            etag: Some("\"abc\"".to_string()),
+            last_modified: Some(time(1_369_353_600_000)),
+            backend: Some("legacy".to_string()),
+            cache_control: Some(Duration::from_secs(60)),
+            cache_time: Duration::from_secs(600),
+        };
+        let at = time(1_700_000_000_000);
+
+        let bytes = encode("a.png?w=100", &entry, at);
+        assert_eq!(peek(bytes.as_slice(), bytes.len() as u64), Some(("a.png?w=100".to_string(), at)));
+        assert_eq!(decode(Bytes::from(bytes)), Some((entry, at)));
+    }
+
+    #[test]
+    fn test_decode_garbage() {
+        assert_eq!(decode(Bytes::from_static(&[1, 2])), None);
+        assert_eq!(decode(Bytes::from_static(&[2, 0, 0, 0, b'{', b'}'])), None);
+        assert_eq!(peek(&[255, 255, 255, 255, 0][..], 5), None);
+        assert_eq!(peek(&[2, 0, 0, 0, b'{', b'}'][..], 5), None);
+    }
+}
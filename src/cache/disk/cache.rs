@@ -0,0 +1,303 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::SystemTime;
+use async_trait::async_trait;
+use lru::LruCache;
+use opentelemetry::metrics::Meter;
+use sha2::{Digest, Sha256};
+use crate::cache::{codec, Cache, Entry, KeyMatcher, Metrics};
+
+const TEMP_SUFFIX: &str = ".tmp";
+
+struct Slot {
+    key: String,
+    size: u64,
+    expires: SystemTime,
+}
+
+struct Index {
+    entries: LruCache<String, Slot>,
+    bytes: u64,
+}
+
+impl Index {
+    /// Removes least recently used entries until the total size fits, returning their names.
+    fn evict(&mut self, max_bytes: u64) -> Vec<String> {
+        let mut evicted = vec![];
+        while self.bytes > max_bytes {
+            let Some((name, slot)) = self.entries.pop_lru() else { break };
+            self.bytes -= slot.size;
+            evicted.push(name);
+        }
+        evicted
+    }
+}
+
+/// Stores entries as files named after the hash of their key, keeping an in-memory LRU index
+/// of them that is rebuilt from the directory on startup.
+pub struct DiskCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    index: Mutex<Index>,
+    metrics: Metrics,
+}
+
+impl DiskCache {
+    pub fn new(dir: PathBuf, max_bytes: u64, meter: &Meter) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+
+        let cache = Self {
+            index: Mutex::new(Index { entries: LruCache::unbounded(), bytes: 0 }),
+            dir,
+            max_bytes,
+            metrics: Metrics::new("disk", meter),
+        };
+        cache.rebuild()?;
+        Ok(cache)
+    }
+
+    /// Indexes the entries left by a previous run, oldest first, dropping expired entries and
+    /// unfinished writes.
+    fn rebuild(&self) -> io::Result<()> {
+        let now = SystemTime::now();
+        let mut found = vec![];
+
+        for file in fs::read_dir(&self.dir)? {
+            let file = file?;
+            let name = file.file_name().to_string_lossy().to_string();
+
+            if name.ends_with(TEMP_SUFFIX) {
+                let _ = fs::remove_file(file.path());
+                continue;
+            }
+            if !is_entry_name(&name) {
+                continue;
+            }
+
+            let meta = file.metadata()?;
+            match fs::File::open(file.path()).ok().and_then(|f| codec::peek(f, meta.len())) {
+                Some((key, expires)) if expires > now => found.push((meta.modified()?, name, key, meta.len(), expires)),
+                _ => { let _ = fs::remove_file(file.path()); }
+            }
+        }
+        found.sort();
+
+        let mut index = self.index.lock().unwrap();
+        for (_, name, key, size, expires) in found {
+            index.entries.put(name, Slot { key, size, expires });
+            index.bytes += size;
+        }
+        for name in index.evict(self.max_bytes) {
+            let _ = fs::remove_file(self.dir.join(name));
+        }
+        Ok(())
+    }
+
+    fn forget(&self, name: &str) {
+        let mut index = self.index.lock().unwrap();
+        if let Some(slot) = index.entries.pop(name) {
+            index.bytes -= slot.size;
+        }
+    }
+}
+
+fn entry_name(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key))
+}
+
+fn is_entry_name(name: &str) -> bool {
+    name.len() == 64 && name.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+#[async_trait]
+impl Cache for DiskCache {
+    async fn get(&self, key: &str) -> Option<Entry> {
+        let name = entry_name(key);
+        let path = self.dir.join(&name);
+
+        let fresh = self.index.lock().unwrap()
+            .entries
+            .get(&name)
+            .map(|slot| slot.expires > SystemTime::now());
+
+        let entry = match fresh {
+            Some(true) => match tokio::fs::read(&path).await.ok().and_then(|b| codec::decode(b.into())) {
+                Some((mut entry, expires)) => {
+                    entry.cache_time = expires.duration_since(SystemTime::now()).unwrap_or_default();
+                    Some(entry)
+                }
+                None => {
+                    self.forget(&name);
+                    None
+                }
+            },
+            Some(false) => {
+                self.forget(&name);
+                let _ = tokio::fs::remove_file(&path).await;
+                None
+            }
+            None => None,
+        };
+
+        match entry {
+            Some(_) => self.metrics.hit(),
+            None => self.metrics.miss(),
+        }
+        entry
+    }
+
+    async fn set(&self, key: &str, entry: Entry) {
+        if entry.cache_time.is_zero() {
+            return;
+        }
+
+        let expires = SystemTime::now() + entry.cache_time;
+        let bytes = codec::encode(key, &entry, expires);
+        let size = bytes.len() as u64;
+        if size > self.max_bytes {
+            return;
+        }
+
+        // Written aside and renamed into place, so that readers never see a partial entry.
+        let name = entry_name(key);
+        let temp = self.dir.join(format!("{}.{:016x}{}", name, rand::random::<u64>(), TEMP_SUFFIX));
+        if tokio::fs::write(&temp, bytes).await.is_err()
+            || tokio::fs::rename(&temp, self.dir.join(&name)).await.is_err() {
+            let _ = tokio::fs::remove_file(&temp).await;
+            return;
+        }
+
+        let evicted = {
+            let mut index = self.index.lock().unwrap();
+            if let Some(replaced) = index.entries.put(name, Slot { key: key.to_string(), size, expires }) {
+                index.bytes -= replaced.size;
+            }
+            index.bytes += size;
+            index.evict(self.max_bytes)
+        };
+
+        if !evicted.is_empty() {
+            self.metrics.evicted(evicted.len() as u64);
+        }
+        for name in evicted {
+            let _ = tokio::fs::remove_file(self.dir.join(name)).await;
+        }
+    }
+
+    async fn purge(&self, matches: &KeyMatcher<'_>) {
+        let purged = {
+            let index = self.index.lock().unwrap();
+            index.entries.iter()
+                .filter(|(_, slot)| matches(&slot.key))
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>()
+        };
+
+        for name in purged {
+            self.forget(&name);
+            let _ = tokio::fs::remove_file(self.dir.join(name)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use axum::body::Bytes;
+    use opentelemetry::metrics::MeterProvider;
+    use tempfile::TempDir;
+    use super::*;
+
+    fn cache(dir: &TempDir, max_bytes: u64) -> DiskCache {
+        DiskCache::new(
+            dir.path().to_path_buf(),
+            max_bytes,
+            &opentelemetry::global::meter_provider().meter("test-meter"),
+        ).unwrap()
+    }
+
+    fn entry(content: &[u8], cache_time: Duration) -> Entry {
+        Entry {
+            content: Bytes::copy_from_slice(content),
+            content_type: Some("image/png".to_string()),
+            etag: Some("\"abc\"".to_string()),
+            last_modified: None,
+            backend: None,
+            cache_control: None,
+            cache_time,
+        }
+    }
+
+    fn files(dir: &TempDir) -> usize {
+        fs::read_dir(dir.path()).unwrap().count()
+    }
+
+    #[tokio::test]
+    async fn test_get_set() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = cache(&dir, 1024);
+        assert_eq!(cache.get("a.jpg?w=100").await, None);
+
+        cache.set("a.jpg?w=100", entry(&[1, 2, 3], Duration::from_secs(60))).await;
+        let hit = cache.get("a.jpg?w=100").await.unwrap();
+        assert_eq!(hit.content, Bytes::from_static(&[1, 2, 3]));
+        assert_eq!(hit.etag, Some("\"abc\"".to_string()));
+        assert!(hit.cache_time <= Duration::from_secs(60) && hit.cache_time > Duration::from_secs(55));
+        assert_eq!(files(&dir), 1);
+    }
+
+    #[tokio::test]
+    async fn test_evicts_least_recently_used() {
+        let dir = tempfile::tempdir().unwrap();
+        let size = codec::encode("a", &entry(&[0; 100], Duration::from_secs(60)), SystemTime::now()).len() as u64;
+        let cache = cache(&dir, size * 2);
+
+        cache.set("a", entry(&[0; 100], Duration::from_secs(60))).await;
+        cache.set("b", entry(&[0; 100], Duration::from_secs(60))).await;
+        cache.get("a").await.unwrap();
+        cache.set("c", entry(&[0; 100], Duration::from_secs(60))).await;
+
+        assert!(cache.get("a").await.is_some());
+        assert!(cache.get("b").await.is_none());
+        assert!(cache.get("c").await.is_some());
+        assert_eq!(files(&dir), 2);
+    }
+
+    #[tokio::test]
+    async fn test_rebuilds_index() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let cache = cache(&dir, 1024);
+            cache.set("a", entry(&[1], Duration::from_secs(60))).await;
+            cache.set("b", entry(&[2], Duration::from_millis(10))).await;
+        }
+        fs::write(dir.path().join(format!("{}.0{}", entry_name("c"), TEMP_SUFFIX)), [0]).unwrap();
+        fs::write(dir.path().join("README"), "not an entry").unwrap();
+        tokio::time::sleep(Duration::from_millis(15)).await;
+
+        let cache = cache(&dir, 1024);
+        assert_eq!(cache.get("a").await.unwrap().content, Bytes::from_static(&[1]));
+        assert!(cache.get("b").await.is_none());
+        // Only the live entry and the foreign file are left behind.
+        assert_eq!(files(&dir), 2);
+    }
+
+    #[tokio::test]
+    async fn test_purge() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let cache = cache(&dir, 1024);
+            cache.set("a.jpg?w=1", entry(&[1], Duration::from_secs(60))).await;
+            cache.set("b.jpg?w=1", entry(&[2], Duration::from_secs(60))).await;
+        }
+
+        // Keys are read back from the entries left by a previous run.
+        let cache = cache(&dir, 1024);
+        cache.purge(&|key| key.starts_with("a.jpg?")).await;
+        assert!(cache.get("a.jpg?w=1").await.is_none());
+        assert!(cache.get("b.jpg?w=1").await.is_some());
+        assert_eq!(files(&dir), 1);
+    }
+}
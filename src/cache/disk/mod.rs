@@ -0,0 +1,3 @@
+pub(crate) mod cache;
+
+pub use cache::DiskCache;
@@ -0,0 +1,186 @@
+use std::sync::Mutex;
+use async_trait::async_trait;
+use lru::LruCache;
+use opentelemetry::metrics::Meter;
+use tokio::time::Instant;
+use crate::cache::{Cache, Entry, KeyMatcher, Metrics};
+
+struct Inner {
+    entries: LruCache<String, (Entry, Instant)>,
+    bytes: usize,
+}
+
+/// Keeps the most recently used entries in process, evicting the least recently used ones
+/// once their content adds up to more than `max_bytes`.
+pub struct MemoryCache {
+    inner: Mutex<Inner>,
+    max_bytes: usize,
+    metrics: Metrics,
+}
+
+impl MemoryCache {
+    pub fn new(max_bytes: usize, meter: &Meter) -> Self {
+        Self {
+            inner: Mutex::new(Inner { entries: LruCache::unbounded(), bytes: 0 }),
+            max_bytes,
+            metrics: Metrics::new("memory", meter),
+        }
+    }
+}
+
+#[async_trait]
+impl Cache for MemoryCache {
+    async fn get(&self, key: &str) -> Option<Entry> {
+        let mut inner = self.inner.lock().unwrap();
+
+        let now = Instant::now();
+        let entry = match inner.entries.get(key) {
+            // Hits are fresh for what is left of the time they were stored for.
+            Some((entry, expires)) if *expires > now => Some(Entry { cache_time: *expires - now, ..entry.clone() }),
+            Some(_) => {
+                let (expired, _) = inner.entries.pop(key).unwrap();
+                inner.bytes -= expired.size();
+                None
+            }
+            None => None,
+        };
+
+        match entry {
+            Some(_) => self.metrics.hit(),
+            None => self.metrics.miss(),
+        }
+        entry
+    }
+
+    async fn set(&self, key: &str, entry: Entry) {
+        if entry.cache_time.is_zero() || entry.size() > self.max_bytes {
+            return;
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        let (size, expires) = (entry.size(), Instant::now() + entry.cache_time);
+
+        if let Some((replaced, _)) = inner.entries.put(key.to_string(), (entry, expires)) {
+            inner.bytes -= replaced.size();
+        }
+        inner.bytes += size;
+
+        let mut evicted = 0;
+        while inner.bytes > self.max_bytes {
+            let Some((_, (entry, _))) = inner.entries.pop_lru() else { break };
+            inner.bytes -= entry.size();
+            evicted += 1;
+        }
+        if evicted > 0 {
+            self.metrics.evicted(evicted);
+        }
+    }
+
+    async fn purge(&self, matches: &KeyMatcher<'_>) {
+        let mut inner = self.inner.lock().unwrap();
+        let purged = inner.entries.iter()
+            .filter(|(key, _)| matches(key))
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+
+        for key in purged {
+            if let Some((entry, _)) = inner.entries.pop(&key) {
+                inner.bytes -= entry.size();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use axum::body::Bytes;
+    use opentelemetry::metrics::MeterProvider;
+    use super::*;
+
+    fn cache(max_bytes: usize) -> MemoryCache {
+        MemoryCache::new(max_bytes, &opentelemetry::global::meter_provider().meter("test-meter"))
+    }
+
+    fn entry(size: usize, cache_time: Duration) -> Entry {
+        Entry {
+            content: Bytes::from(vec![0; size]),
+            content_type: Some("image/png".to_string()),
+            etag: None,
+            last_modified: None,
+            backend: None,
+            cache_control: None,
+            cache_time,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_set() {
+        let cache = cache(100);
+        assert_eq!(cache.get("a").await, None);
+
+        cache.set("a", entry(10, Duration::from_secs(60))).await;
+        let hit = cache.get("a").await.unwrap();
+        assert_eq!(hit, entry(10, hit.cache_time));
+
+        cache.set("a", entry(20, Duration::from_secs(60))).await;
+        assert_eq!(cache.get("a").await.unwrap().size(), 20);
+        assert_eq!(cache.inner.lock().unwrap().bytes, 20);
+    }
+
+    #[tokio::test]
+    async fn test_purge() {
+        let cache = cache(100);
+        cache.set("a.jpg?w=1", entry(10, Duration::from_secs(60))).await;
+        cache.set("a.jpg?w=2", entry(10, Duration::from_secs(60))).await;
+        cache.set("b.jpg?w=1", entry(10, Duration::from_secs(60))).await;
+
+        cache.purge(&|key| key.starts_with("a.jpg?")).await;
+        assert!(cache.get("a.jpg?w=1").await.is_none());
+        assert!(cache.get("a.jpg?w=2").await.is_none());
+        assert!(cache.get("b.jpg?w=1").await.is_some());
+        assert_eq!(cache.inner.lock().unwrap().bytes, 10);
+    }
+
+    #[tokio::test]
+    async fn test_evicts_least_recently_used_by_size() {
+        let cache = cache(100);
+        cache.set("a", entry(40, Duration::from_secs(60))).await;
+        cache.set("b", entry(40, Duration::from_secs(60))).await;
+        cache.get("a").await.unwrap();
+
+        cache.set("c", entry(40, Duration::from_secs(60))).await;
+        assert!(cache.get("a").await.is_some());
+        assert!(cache.get("b").await.is_none());
+        assert!(cache.get("c").await.is_some());
+        assert_eq!(cache.inner.lock().unwrap().bytes, 80);
+
+        // Entries larger than the whole cache are never stored.
+        cache.set("d", entry(101, Duration::from_secs(60))).await;
+        assert!(cache.get("d").await.is_none());
+        assert!(cache.get("a").await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_get_remaining_cache_time() {
+        let cache = cache(100);
+        cache.set("a", entry(10, Duration::from_secs(60))).await;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let cache_time = cache.get("a").await.unwrap().cache_time;
+        assert!(cache_time <= Duration::from_millis(59_980) && cache_time > Duration::from_secs(50));
+    }
+
+    #[tokio::test]
+    async fn test_expires() {
+        let cache = cache(100);
+        cache.set("a", entry(10, Duration::from_millis(20))).await;
+        cache.set("b", entry(10, Duration::ZERO)).await;
+
+        assert!(cache.get("a").await.is_some());
+        assert!(cache.get("b").await.is_none());
+        tokio::time::sleep(Duration::from_millis(25)).await;
+        assert!(cache.get("a").await.is_none());
+        assert_eq!(cache.inner.lock().unwrap().bytes, 0);
+    }
+}
@@ -0,0 +1,76 @@
+use std::time::{Duration, SystemTime};
+use async_trait::async_trait;
+use axum::body::Bytes;
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::{Counter, Meter};
+
+pub(crate) mod codec;
+pub(crate) mod memory;
+pub(crate) mod disk;
+pub(crate) mod noop;
+pub(crate) mod redis;
+pub(crate) mod tiered;
+
+/// A rendered image along with what is needed to serve it again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub content: Bytes,
+    pub content_type: Option<String>,
+    pub etag: Option<String>,
+    pub last_modified: Option<SystemTime>,
+    pub backend: Option<String>,
+    /// Max-age sent by the origin, so that hits are answered with the same Cache-Control.
+    pub cache_control: Option<Duration>,
+    /// How long the entry stays fresh, both in the cache and for clients.
+    pub cache_time: Duration,
+}
+
+impl Entry {
+    pub fn size(&self) -> usize {
+        self.content.len()
+    }
+}
+
+/// Selects entries by their key.
+pub type KeyMatcher<'a> = dyn Fn(&str) -> bool + Send + Sync + 'a;
+
+#[async_trait]
+pub trait Cache {
+    async fn get(&self, key: &str) -> Option<Entry>;
+
+    async fn set(&self, key: &str, entry: Entry);
+
+    /// Removes every entry whose key matches, e.g. the variants of a purged image.
+    async fn purge(&self, matches: &KeyMatcher<'_>);
+}
+
+/// Hit, miss and eviction counters shared by the cache backends.
+pub(crate) struct Metrics {
+    hits: Counter<u64>,
+    misses: Counter<u64>,
+    evictions: Counter<u64>,
+    attributes: [KeyValue; 1],
+}
+
+impl Metrics {
+    pub fn new(cache: &'static str, meter: &Meter) -> Self {
+        Self {
+            hits: meter.u64_counter("cache_hits")
+                .with_description("Number of lookups answered by the cache")
+                .init(),
+            misses: meter.u64_counter("cache_misses")
+                .with_description("Number of lookups not found in the cache")
+                .init(),
+            evictions: meter.u64_counter("cache_evictions")
+                .with_description("Number of entries evicted to make room for new ones")
+                .init(),
+            attributes: [KeyValue::new("cache", cache)],
+        }
+    }
+
+    pub fn hit(&self) { self.hits.add(1, &self.attributes); }
+
+    pub fn miss(&self) { self.misses.add(1, &self.attributes); }
+
+    pub fn evicted(&self, count: u64) { self.evictions.add(count, &self.attributes); }
+}
@@ -0,0 +1,16 @@
+use async_trait::async_trait;
+use crate::cache::{Cache, Entry, KeyMatcher};
+
+/// Used when no cache is configured.
+pub struct NoCache;
+
+#[async_trait]
+impl Cache for NoCache {
+    async fn get(&self, _: &str) -> Option<Entry> {
+        None
+    }
+
+    async fn set(&self, _: &str, _: Entry) {}
+
+    async fn purge(&self, _: &KeyMatcher<'_>) {}
+}
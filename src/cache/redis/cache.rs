@@ -0,0 +1,280 @@
+use std::io::{Read, Write};
+use std::time::{Duration, SystemTime};
+use async_trait::async_trait;
+use flate2::Compression;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use opentelemetry::metrics::Meter;
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, Client, RedisResult};
+use tokio::sync::Mutex;
+use tokio::time::{timeout, Instant};
+use crate::cache::{codec, Cache, Entry, KeyMatcher, Metrics};
+use crate::config::config::RedisCache as Config;
+
+/// How long to wait before trying to connect again after failing to.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Number of keys to ask for with each `SCAN` while purging.
+const SCAN_COUNT: usize = 1000;
+
+/// How many times `max_value_bytes` an entry may take once inflated, so that corrupt values
+/// cannot inflate without bound.
+const MAX_INFLATION: usize = 16;
+
+const RAW: u8 = 0;
+const DEFLATE: u8 = 1;
+
+#[derive(Default)]
+struct State {
+    connection: Option<ConnectionManager>,
+    retry_at: Option<Instant>,
+}
+
+/// Stores entries in Redis so that replicas share them. Every failure to reach Redis is
+/// treated as a miss, so the proxy keeps serving, uncached, while Redis is unavailable.
+pub struct RedisCache {
+    client: Client,
+    state: Mutex<State>,
+    cfg: Config,
+    metrics: Metrics,
+}
+
+impl RedisCache {
+    pub fn new(cfg: Config, meter: &Meter) -> RedisResult<Self> {
+        Ok(Self {
+            client: Client::open(cfg.url.as_str())?,
+            state: Mutex::new(State::default()),
+            cfg,
+            metrics: Metrics::new("redis", meter),
+        })
+    }
+
+    async fn connection(&self) -> Option<ConnectionManager> {
+        let mut state = self.state.lock().await;
+        if let Some(connection) = &state.connection {
+            return Some(connection.clone());
+        }
+        if state.retry_at.is_some_and(|at| Instant::now() < at) {
+            return None;
+        }
+
+        match timeout(self.cfg.timeout, ConnectionManager::new(self.client.clone())).await {
+            Ok(Ok(connection)) => {
+                state.connection = Some(connection.clone());
+                Some(connection)
+            }
+            _ => {
+                state.retry_at = Some(Instant::now() + RECONNECT_INTERVAL);
+                None
+            }
+        }
+    }
+
+    fn max_unpacked_bytes(&self) -> usize {
+        self.cfg.max_value_bytes.saturating_mul(MAX_INFLATION)
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.cfg.key_prefix, key)
+    }
+
+    async fn fetch(&self, key: &str) -> Option<Entry> {
+        let mut connection = self.connection().await?;
+        let value: Option<Vec<u8>> = timeout(self.cfg.timeout, connection.get(self.key(key))).await.ok()?.ok()?;
+
+        let (mut entry, expires) = codec::decode(unpack(&value?, self.max_unpacked_bytes())?.into())?;
+        entry.cache_time = expires.duration_since(SystemTime::now()).ok()?;
+        Some(entry)
+    }
+}
+
+/// Prefixes the encoded entry with whether it is compressed, so that replicas configured
+/// differently can still read each other's entries.
+fn pack(bytes: Vec<u8>, compress: bool) -> Vec<u8> {
+    if !compress {
+        return [vec![RAW], bytes].concat();
+    }
+
+    let mut encoder = DeflateEncoder::new(vec![DEFLATE], Compression::fast());
+    encoder.write_all(&bytes).expect("writing to a vec cannot fail");
+    encoder.finish().expect("writing to a vec cannot fail")
+}
+
+/// Returns `None` for values that are not packed entries or that inflate past `max_bytes`.
+fn unpack(value: &[u8], max_bytes: usize) -> Option<Vec<u8>> {
+    match value.split_first()? {
+        (&RAW, bytes) => Some(bytes.to_vec()),
+        (&DEFLATE, bytes) => {
+            let mut decoded = vec![];
+            DeflateDecoder::new(bytes).take(max_bytes as u64 + 1).read_to_end(&mut decoded).ok()?;
+            Some(decoded).filter(|decoded| decoded.len() <= max_bytes)
+        }
+        _ => None,
+    }
+}
+
+#[async_trait]
+impl Cache for RedisCache {
+    async fn get(&self, key: &str) -> Option<Entry> {
+        let entry = self.fetch(key).await;
+
+        match entry {
+            Some(_) => self.metrics.hit(),
+            None => self.metrics.miss(),
+        }
+        entry
+    }
+
+    async fn set(&self, key: &str, entry: Entry) {
+        if entry.cache_time.is_zero() {
+            return;
+        }
+
+        let bytes = codec::encode(key, &entry, SystemTime::now() + entry.cache_time);
+        if bytes.len() > self.max_unpacked_bytes() {
+            return;
+        }
+        let value = pack(bytes, self.cfg.compress);
+        if value.len() > self.cfg.max_value_bytes {
+            return;
+        }
+
+        if let Some(mut connection) = self.connection().await {
+            let ttl = entry.cache_time.as_millis() as u64;
+            let _ = timeout(
+                self.cfg.timeout,
+                redis::cmd("SET").arg(self.key(key)).arg(value).arg("PX").arg(ttl)
+                    .query_async::<_, ()>(&mut connection),
+            ).await;
+        }
+    }
+
+    /// Scans the keys under the prefix a batch at a time, deleting the matching ones. Purges
+    /// are shared with the other replicas, like the entries.
+    async fn purge(&self, matches: &KeyMatcher<'_>) {
+        let Some(mut connection) = self.connection().await else { return };
+        let pattern = format!("{}*", escape_pattern(&self.cfg.key_prefix));
+
+        let mut cursor = 0;
+        loop {
+            let mut scan = redis::cmd("SCAN");
+            scan.arg(cursor).arg("MATCH").arg(&pattern).arg("COUNT").arg(SCAN_COUNT);
+            let scanned = timeout(self.cfg.timeout, scan.query_async::<_, (u64, Vec<String>)>(&mut connection)).await;
+            let Ok(Ok((next, keys))) = scanned else { return };
+
+            let purged = keys.into_iter()
+                .filter(|key| key.strip_prefix(&self.cfg.key_prefix).is_some_and(matches))
+                .collect::<Vec<_>>();
+            if !purged.is_empty() {
+                let _ = timeout(self.cfg.timeout, connection.del::<_, ()>(purged)).await;
+            }
+
+            if next == 0 {
+                return;
+            }
+            cursor = next;
+        }
+    }
+}
+
+/// Escapes the characters that `SCAN MATCH` patterns treat specially.
+fn escape_pattern(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Bytes;
+    use opentelemetry::metrics::MeterProvider;
+    use super::*;
+
+    fn cache(url: &str, compress: bool) -> RedisCache {
+        RedisCache::new(Config {
+            url: url.to_string(),
+            key_prefix: format!("darkroom-test-{}:", rand::random::<u32>()),
+            max_value_bytes: 1024,
+            compress,
+            timeout: Duration::from_millis(100),
+        }, &opentelemetry::global::meter_provider().meter("test-meter")).unwrap()
+    }
+
+    fn entry(content: Vec<u8>) -> Entry {
+        Entry {
+            content: Bytes::from(content),
+            content_type: Some("image/webp".to_string()),
+            etag: Some("\"abc\"".to_string()),
+            last_modified: None,
+            backend: None,
+            cache_control: None,
+            cache_time: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn test_escape_pattern() {
+        assert_eq!(escape_pattern("darkroom:"), "darkroom:");
+        assert_eq!(escape_pattern("a*b?[c]\\"), "a\\*b\\?\\[c\\]\\\\");
+    }
+
+    #[test]
+    fn test_pack() {
+        let bytes = vec![7; 512];
+        assert_eq!(pack(bytes.clone(), false).len(), 513);
+        assert!(pack(bytes.clone(), true).len() < 512);
+
+        assert_eq!(unpack(&pack(bytes.clone(), false), 512), Some(bytes.clone()));
+        assert_eq!(unpack(&pack(bytes.clone(), true), 512), Some(bytes.clone()));
+        assert_eq!(unpack(&[9, 1, 2], 512), None);
+        assert_eq!(unpack(&[], 512), None);
+
+        // Values inflating past the limit are misses.
+        assert_eq!(unpack(&pack(bytes, true), 511), None);
+    }
+
+    #[tokio::test]
+    async fn test_unreachable() {
+        // Nothing listens on the discard port.
+        let cache = cache("redis://127.0.0.1:9/", false);
+
+        let started = Instant::now();
+        cache.set("a.jpg?w=100", entry(vec![1])).await;
+        assert_eq!(cache.get("a.jpg?w=100").await, None);
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(cache.state.lock().await.retry_at.is_some());
+    }
+
+    /// Runs against the server at `REDIS_URL`, e.g. a local `redis-server`, with
+    /// `cargo test -- --ignored`.
+    #[tokio::test]
+    #[ignore = "needs a redis server at REDIS_URL"]
+    async fn test_get_set() {
+        let url = std::env::var("REDIS_URL").expect("REDIS_URL must point to a redis server");
+
+        for compress in [false, true] {
+            let cache = cache(&url, compress);
+            assert_eq!(cache.get("a.jpg?w=100").await, None);
+
+            cache.set("a.jpg?w=100", entry(vec![1, 2, 3])).await;
+            let hit = cache.get("a.jpg?w=100").await.unwrap();
+            assert_eq!(hit.content, Bytes::from_static(&[1, 2, 3]));
+            assert_eq!(hit.etag, Some("\"abc\"".to_string()));
+
+            // Values over the limit are not stored.
+            cache.set("b.jpg?w=100", entry(vec![0; 2048].into_iter().map(|_| rand::random()).collect())).await;
+            assert_eq!(cache.get("b.jpg?w=100").await, None);
+
+            cache.set("c.jpg?w=100", entry(vec![4])).await;
+            cache.purge(&|key| key.starts_with("a.jpg?")).await;
+            assert_eq!(cache.get("a.jpg?w=100").await, None);
+            assert!(cache.get("c.jpg?w=100").await.is_some());
+        }
+    }
+}
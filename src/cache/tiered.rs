@@ -0,0 +1,76 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+use crate::cache::{Cache, Entry, KeyMatcher};
+
+/// Looks entries up in each tier in turn, copying hits into the faster tiers in front of it.
+pub struct TieredCache {
+    tiers: Vec<Arc<dyn Cache + Send + Sync>>,
+}
+
+impl TieredCache {
+    pub fn new(tiers: Vec<Arc<dyn Cache + Send + Sync>>) -> Self {
+        Self { tiers }
+    }
+}
+
+#[async_trait]
+impl Cache for TieredCache {
+    async fn get(&self, key: &str) -> Option<Entry> {
+        for (i, tier) in self.tiers.iter().enumerate() {
+            if let Some(entry) = tier.get(key).await {
+                for faster in &self.tiers[..i] {
+                    faster.set(key, entry.clone()).await;
+                }
+                return Some(entry);
+            }
+        }
+        None
+    }
+
+    async fn set(&self, key: &str, entry: Entry) {
+        for tier in &self.tiers {
+            tier.set(key, entry.clone()).await;
+        }
+    }
+
+    async fn purge(&self, matches: &KeyMatcher<'_>) {
+        for tier in &self.tiers {
+            tier.purge(matches).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use axum::body::Bytes;
+    use opentelemetry::metrics::MeterProvider;
+    use crate::cache::memory::MemoryCache;
+    use super::*;
+
+    fn memory() -> Arc<MemoryCache> {
+        Arc::new(MemoryCache::new(1024, &opentelemetry::global::meter_provider().meter("test-meter")))
+    }
+
+    #[tokio::test]
+    async fn test_promotes_hits() {
+        let (fast, slow) = (memory(), memory());
+        let cache = TieredCache::new(vec![fast.clone(), slow.clone()]);
+        let entry = Entry {
+            content: Bytes::from_static(&[1]),
+            content_type: None,
+            etag: None,
+            last_modified: None,
+            backend: None,
+            cache_control: None,
+            cache_time: Duration::from_secs(60),
+        };
+
+        slow.set("a", entry.clone()).await;
+        assert_eq!(fast.get("a").await, None);
+
+        assert_eq!(cache.get("a").await.map(|e| e.content), Some(entry.content.clone()));
+        assert_eq!(fast.get("a").await.map(|e| e.content), Some(entry.content));
+        assert_eq!(cache.get("b").await, None);
+    }
+}
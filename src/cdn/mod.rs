@@ -0,0 +1,11 @@
+pub(crate) mod webhook;
+
+use async_trait::async_trait;
+
+pub use webhook::WebhookPurger;
+
+/// Invalidates everything a CDN has cached under the given surrogate keys.
+#[async_trait]
+pub trait Purger {
+    async fn purge(&self, keys: &[String]) -> Result<(), reqwest::Error>;
+}
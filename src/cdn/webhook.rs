@@ -0,0 +1,43 @@
+use async_trait::async_trait;
+use axum::http::header::CONTENT_TYPE;
+use reqwest::Client;
+use serde::Serialize;
+use crate::cdn::Purger;
+use crate::config::Webhook;
+
+#[derive(Serialize)]
+struct Body<'a> {
+    surrogate_keys: &'a [String],
+}
+
+/// Posts the keys to purge as `{"surrogate_keys": [...]}` to an endpoint that forwards
+/// them to the CDN, along with the configured headers, e.g. the CDN's API credentials.
+pub struct WebhookPurger {
+    cfg: Webhook,
+    client: Client,
+}
+
+impl WebhookPurger {
+    pub fn new(cfg: Webhook) -> Result<Self, reqwest::Error> {
+        let client = Client::builder().timeout(cfg.timeout).build()?;
+        Ok(Self { cfg, client })
+    }
+}
+
+#[async_trait]
+impl Purger for WebhookPurger {
+    async fn purge(&self, keys: &[String]) -> Result<(), reqwest::Error> {
+        let body = serde_json::to_vec(&Body { surrogate_keys: keys })
+            .expect("keys are serializable");
+
+        let mut builder = self.client.post(self.cfg.url.as_str())
+            .header(CONTENT_TYPE, "application/json")
+            .body(body);
+        for (name, value) in &self.cfg.headers {
+            builder = builder.header(name, value);
+        }
+
+        builder.send().await?.error_for_status()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use futures::future::{BoxFuture, FutureExt, Shared};
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::{Counter, Meter};
+
+type Calls<K, V> = Arc<Mutex<HashMap<K, Call<V>>>>;
+
+struct Call<V> {
+    fut: Shared<BoxFuture<'static, V>>,
+    waiters: usize,
+}
+
+/// Held by everyone awaiting a call. The last one to finish, or to give up waiting, removes the
+/// call, so that calls nobody awaits anymore are dropped instead of lingering in the map.
+struct Waiter<K: Eq + Hash, V> {
+    calls: Calls<K, V>,
+    key: K,
+}
+
+impl<K: Eq + Hash, V> Drop for Waiter<K, V> {
+    fn drop(&mut self) {
+        let mut calls = self.calls.lock().unwrap();
+        if let Some(call) = calls.get_mut(&self.key) {
+            call.waiters -= 1;
+            if call.waiters == 0 {
+                calls.remove(&self.key);
+            }
+        }
+    }
+}
+
+/// Deduplicates concurrent calls for the same key, so that only the first caller runs the
+/// future and everyone arriving while it is in flight shares its output.
+pub struct Coalescer<K, V> {
+    calls: Calls<K, V>,
+    counter: Counter<u64>,
+    attributes: [KeyValue; 1],
+}
+
+impl<K, V> Coalescer<K, V>
+where
+    K: Eq + Hash + Clone + Send + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    pub fn new(layer: &'static str, meter: &Meter) -> Self {
+        Self {
+            calls: Arc::new(Mutex::new(HashMap::new())),
+            counter: meter.u64_counter("coalesced_requests")
+                .with_description("Number of requests that shared the result of an in-flight request")
+                .init(),
+            attributes: [KeyValue::new("layer", layer)],
+        }
+    }
+
+    pub async fn run<F>(&self, key: K, fut: F) -> V
+    where
+        F: Future<Output=V> + Send + 'static,
+    {
+        let call = {
+            let mut calls = self.calls.lock().unwrap();
+            match calls.get_mut(&key) {
+                Some(call) => {
+                    self.counter.add(1, &self.attributes);
+                    call.waiters += 1;
+                    call.fut.clone()
+                }
+                None => {
+                    let call = fut.boxed().shared();
+                    calls.insert(key.clone(), Call { fut: call.clone(), waiters: 1 });
+                    call
+                }
+            }
+        };
+
+        let _waiter = Waiter { calls: self.calls.clone(), key };
+        call.await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use opentelemetry::metrics::MeterProvider;
+    use super::*;
+
+    fn coalescer() -> Coalescer<&'static str, usize> {
+        Coalescer::new("test", &opentelemetry::global::meter_provider().meter("test-meter"))
+    }
+
+    fn call(calls: &Arc<AtomicUsize>) -> impl Future<Output=usize> + Send + 'static {
+        let calls = calls.clone();
+        async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            calls.fetch_add(1, Ordering::SeqCst) + 1
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_shares_in_flight_calls() {
+        let coalescer = coalescer();
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let outputs = futures::future::join_all(
+            (0..10).map(|_| coalescer.run("a", call(&calls)))
+        ).await;
+
+        assert_eq!(outputs, vec![1; 10]);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(coalescer.calls.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_run_keys_are_independent() {
+        let coalescer = coalescer();
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let (a, b) = tokio::join!(coalescer.run("a", call(&calls)), coalescer.run("b", call(&calls)));
+
+        assert_eq!(a + b, 3);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_run_abandoned() {
+        let coalescer = coalescer();
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let (a1, a2) = (coalescer.run("a", call(&calls)), coalescer.run("a", call(&calls)));
+        let _ = tokio::time::timeout(Duration::from_millis(5), futures::future::join(a1, a2)).await;
+
+        assert!(coalescer.calls.lock().unwrap().is_empty());
+        assert_eq!(coalescer.run("a", call(&calls)).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_run_after_completion() {
+        let coalescer = coalescer();
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        assert_eq!(coalescer.run("a", call(&calls)).await, 1);
+        assert_eq!(coalescer.run("a", call(&calls)).await, 2);
+    }
+}
@@ -0,0 +1,48 @@
+use serde::Deserialize;
+
+/// An RGB color written as 6 hex digits, optionally prefixed with `#`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Color(pub [u8; 3]);
+
+impl Color {
+    pub fn parse(hex: &str) -> Option<Color> {
+        let hex = hex.trim_start_matches('#');
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        Some(Color([channel(0)?, channel(2)?, channel(4)?]))
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let hex = String::deserialize(deserializer)?;
+        Color::parse(&hex).ok_or_else(|| serde::de::Error::custom(format!("invalid color: {}", hex)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let testcases = vec![
+            ("ffffff", Some(Color([255, 255, 255]))),
+            ("#1a2B3c", Some(Color([0x1a, 0x2b, 0x3c]))),
+            ("fff", None),
+            ("gggggg", None),
+            ("ffffffff", None),
+            ("", None),
+        ];
+
+        for (hex, expected) in testcases {
+            assert_eq!(Color::parse(hex), expected, "{}", hex);
+        }
+    }
+}
@@ -1,30 +1,64 @@
 use std::{env};
+use std::collections::HashMap;
 use std::net::{SocketAddr};
-use std::str::FromStr;
+use std::path::PathBuf;
 use std::time::Duration;
 use config::{ConfigError, Environment, File, FileFormat, FileSourceFile};
-use serde::{Deserialize};
+use serde::{Deserialize, Deserializer};
+use serde::de::Error as _;
+use crate::config::color::Color;
 use crate::config::url::Url;
+use crate::handler::query::Format;
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
+    #[allow(dead_code)]
     pub log: Log,
-    pub http: HTTP,
-    pub source: Source,
+    pub http: Http,
+    pub source: Option<Source>,
+    #[serde(default)]
+    pub sources: Vec<Source>,
     pub handler: Handler,
+    #[serde(default)]
+    pub cache: Cache,
+    pub admin: Option<Admin>,
+    #[serde(default)]
+    pub pool: Pool,
+    #[serde(default)]
+    pub limits: Limits,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Source {
     pub kind: SourceKind,
+    pub name: Option<String>,
+    pub pattern: Option<String>,
     pub web_folder: Option<WebFolder>,
+    pub s3: Option<S3>,
+    pub file_system: Option<FileSystem>,
+    pub fallback: Option<Vec<Source>>,
     pub path_prefix: Option<String>,
+    #[serde(default)]
     pub network: Network,
+    pub retry: Option<Retry>,
+    pub circuit_breaker: Option<CircuitBreaker>,
+    pub stale_if_error: Option<StaleIfError>,
+    #[serde(default, with = "humantime_serde")]
+    pub cache_duration: Option<Duration>,
 }
 
 #[derive(Debug, Deserialize, PartialEq, Clone)]
 pub enum SourceKind {
     WebFolder,
+    S3,
+    FileSystem,
+    Fallback,
+}
+
+impl Source {
+    pub fn name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| format!("{:?}", self.kind).to_lowercase())
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -32,7 +66,72 @@ pub struct WebFolder {
     pub base_url: Url,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
+pub struct S3 {
+    pub bucket: String,
+    pub region: String,
+    pub endpoint: Option<Url>,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+    pub force_path_style: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct FileSystem {
+    pub root: PathBuf,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Retry {
+    pub max_attempts: u32,
+    #[serde(with = "humantime_serde")]
+    pub base_delay: Duration,
+    #[serde(with = "humantime_serde")]
+    pub max_delay: Duration,
+    #[serde(default)]
+    pub jitter: bool,
+    #[serde(default = "Retry::default_retryable_status_codes")]
+    pub retryable_status_codes: Vec<u16>,
+    /// Budget for all attempts together. Defaults to the source's network timeout, which
+    /// applies to each attempt, times `max_attempts` plus `max_delay` between attempts.
+    #[serde(default, with = "humantime_serde")]
+    pub deadline: Option<Duration>,
+}
+
+impl Retry {
+    fn default_retryable_status_codes() -> Vec<u16> { vec![502, 503, 504] }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct CircuitBreaker {
+    /// Ratio of failed requests within the window at which the circuit opens.
+    pub failure_ratio: f64,
+    /// Minimum number of requests within the window before the ratio is considered.
+    pub min_requests: u32,
+    #[serde(with = "humantime_serde")]
+    pub window: Duration,
+    #[serde(with = "humantime_serde")]
+    pub open_duration: Duration,
+    /// Number of successful probes needed in the half-open state to close the circuit.
+    #[serde(default = "CircuitBreaker::default_half_open_probes")]
+    pub half_open_probes: u32,
+}
+
+impl CircuitBreaker {
+    fn default_half_open_probes() -> u32 { 1 }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct StaleIfError {
+    /// Total size of the last fetched copies kept for serving while the source fails.
+    pub max_bytes: usize,
+    /// Serves the stale copy instead of waiting longer than this for the source.
+    #[serde(default, with = "humantime_serde")]
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Debug, Default, Deserialize)]
 pub struct Network {
     pub config: NetworkConfig,
 }
@@ -41,8 +140,31 @@ pub struct Network {
 pub struct NetworkConfig {
     #[serde(with = "humantime_serde")]
     pub timeout: Duration,
+    #[serde(default, with = "humantime_serde")]
+    pub connect_timeout: Option<Duration>,
+    #[serde(default, with = "humantime_serde")]
+    pub pool_idle_timeout: Option<Duration>,
+    pub pool_max_idle_per_host: Option<usize>,
+    pub http2_prior_knowledge: Option<bool>,
+    pub proxy: Option<Url>,
+    pub user_agent: Option<String>,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        NetworkConfig {
+            timeout: Duration::from_secs(5),
+            connect_timeout: None,
+            pool_idle_timeout: None,
+            pool_max_idle_per_host: None,
+            http2_prior_knowledge: None,
+            proxy: None,
+            user_agent: None,
+        }
+    }
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Deserialize)]
 pub struct Log {
     pub enabled: Option<bool>,
@@ -50,7 +172,8 @@ pub struct Log {
 }
 
 #[derive(Debug, Deserialize)]
-pub struct HTTP {
+pub struct Http {
+    #[allow(dead_code)]
     pub debug_mode: Option<bool>,
     pub bind_address: SocketAddr,
 }
@@ -58,12 +181,200 @@ pub struct HTTP {
 #[derive(Debug, Default, Deserialize)]
 pub struct Handler {
     pub response: Response,
+    #[serde(default)]
+    pub output: Output,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Output {
+    /// Color that translucent images are flattened onto when encoding to a format without alpha.
+    #[serde(default = "Output::default_background")]
+    pub background: Color,
+    /// Formats `auto=format` picks from, most preferred first, when the client accepts them.
+    #[serde(default = "Output::default_auto_formats")]
+    pub auto_formats: Vec<Format>,
+    /// AVIF encoder speed, from 1, the slowest and smallest output, to 10, the fastest.
+    #[serde(default = "Output::default_avif_speed", deserialize_with = "Output::deserialize_avif_speed")]
+    pub avif_speed: u8,
+}
+
+impl Output {
+    fn default_background() -> Color { Color([255, 255, 255]) }
+
+    fn default_auto_formats() -> Vec<Format> { vec![Format::Avif, Format::WebP] }
+
+    fn default_avif_speed() -> u8 { 4 }
+
+    /// Refuses speeds the encoder does not support, which it would panic on when encoding.
+    fn deserialize_avif_speed<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error> {
+        match u8::deserialize(deserializer)? {
+            speed @ 1..=10 => Ok(speed),
+            speed => Err(D::Error::custom(format!("avif_speed must be between 1 and 10, got {}", speed))),
+        }
+    }
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Output {
+            background: Output::default_background(),
+            auto_formats: Output::default_auto_formats(),
+            avif_speed: Output::default_avif_speed(),
+        }
+    }
 }
 
 #[derive(Debug, Default, Deserialize)]
 pub struct Response {
     #[serde(with = "humantime_serde")]
     pub cache_duration: Duration,
+    #[serde(default)]
+    pub upstream_max_age: UpstreamMaxAge,
+    /// Directives telling caches how long they may serve a stale image while revalidating it
+    /// in the background, or while the proxy is failing.
+    #[serde(default, with = "humantime_serde")]
+    pub stale_while_revalidate: Option<Duration>,
+    #[serde(default, with = "humantime_serde")]
+    pub stale_if_error: Option<Duration>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Cache {
+    pub memory: Option<MemoryCache>,
+    pub disk: Option<DiskCache>,
+    pub redis: Option<RedisCache>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MemoryCache {
+    pub max_bytes: usize,
+}
+
+/// Persists both fetched originals and rendered images under `path`.
+#[derive(Debug, Deserialize)]
+pub struct DiskCache {
+    pub path: PathBuf,
+    pub max_bytes: u64,
+}
+
+/// Bounds the threads decoding, processing and encoding images.
+#[derive(Debug, Deserialize, Clone)]
+pub struct Pool {
+    #[serde(default = "Pool::default_size")]
+    pub size: usize,
+    /// Number of images waiting for a thread beyond which requests are shed with a 503.
+    #[serde(default = "Pool::default_queue_depth")]
+    pub queue_depth: usize,
+}
+
+impl Pool {
+    fn default_size() -> usize { std::thread::available_parallelism().map_or(1, |n| n.get()) }
+
+    fn default_queue_depth() -> usize { 64 }
+}
+
+impl Default for Pool {
+    fn default() -> Self {
+        Pool { size: Pool::default_size(), queue_depth: Pool::default_queue_depth() }
+    }
+}
+
+/// Protects against sources and requests that would take too much memory to render.
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub struct Limits {
+    /// Sources over this size are refused while fetching them, passthrough requests are not
+    /// limited.
+    pub max_source_bytes: Option<u64>,
+    #[serde(default = "Limits::default_max_dimension")]
+    pub max_width: u32,
+    #[serde(default = "Limits::default_max_dimension")]
+    pub max_height: u32,
+    /// Largest allocation the decoder may make, the decoded image buffer included.
+    #[serde(default = "Limits::default_max_alloc")]
+    pub max_alloc: u64,
+    pub max_output_width: Option<u16>,
+    pub max_output_height: Option<u16>,
+    /// Most frames decoded from an animated source.
+    #[serde(default = "Limits::default_max_frames")]
+    pub max_frames: u32,
+    /// Most pixels decoded from an animated source, summed over its frames.
+    #[serde(default = "Limits::default_max_animation_pixels")]
+    pub max_animation_pixels: u64,
+}
+
+impl Limits {
+    fn default_max_dimension() -> u32 { 16384 }
+
+    fn default_max_alloc() -> u64 { 512 * 1024 * 1024 }
+
+    fn default_max_frames() -> u32 { 500 }
+
+    fn default_max_animation_pixels() -> u64 { 64 * 1024 * 1024 }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_source_bytes: None,
+            max_width: Limits::default_max_dimension(),
+            max_height: Limits::default_max_dimension(),
+            max_alloc: Limits::default_max_alloc(),
+            max_output_width: None,
+            max_output_height: None,
+            max_frames: Limits::default_max_frames(),
+            max_animation_pixels: Limits::default_max_animation_pixels(),
+        }
+    }
+}
+
+/// Enables the admin routes, which require the token as a bearer token.
+#[derive(Debug, Deserialize)]
+pub struct Admin {
+    pub token: String,
+    /// Receives the surrogate keys to purge from the CDN.
+    pub purge: Webhook,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Webhook {
+    pub url: Url,
+    /// Sent along with every call, e.g. for authenticating with the CDN's API.
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(default = "Webhook::default_timeout", with = "humantime_serde")]
+    pub timeout: Duration,
+}
+
+impl Webhook {
+    fn default_timeout() -> Duration { Duration::from_secs(5) }
+}
+
+/// Shares rendered images between replicas.
+#[derive(Debug, Deserialize, Clone)]
+pub struct RedisCache {
+    pub url: String,
+    #[serde(default = "RedisCache::default_key_prefix")]
+    pub key_prefix: String,
+    pub max_value_bytes: usize,
+    #[serde(default)]
+    pub compress: bool,
+    #[serde(default = "RedisCache::default_timeout", with = "humantime_serde")]
+    pub timeout: Duration,
+}
+
+impl RedisCache {
+    fn default_key_prefix() -> String { "darkroom:".to_string() }
+
+    fn default_timeout() -> Duration { Duration::from_millis(100) }
+}
+
+/// How a `Cache-Control: max-age` sent by the origin affects the response cache duration.
+#[derive(Debug, Default, Deserialize, PartialEq, Clone, Copy)]
+pub enum UpstreamMaxAge {
+    Ignore,
+    Override,
+    #[default]
+    Cap,
 }
 
 impl Config {
@@ -79,6 +390,12 @@ impl Config {
         ])
     }
 
+    /// Returns the configured sources in the order they are matched, the single `source`
+    /// being the last one so that it acts as a catch-all.
+    pub fn sources(&self) -> impl Iterator<Item=&Source> {
+        self.sources.iter().chain(self.source.iter())
+    }
+
     fn parse(sources: Vec<File<FileSourceFile, FileFormat>>) -> Result<Self, ConfigError> {
         sources
             .iter()
@@ -95,6 +412,7 @@ impl Config {
 
 #[cfg(test)]
 mod tests {
+    use std::str::FromStr;
     use super::*;
 
     #[test]
@@ -104,10 +422,28 @@ mod tests {
             ("HTTP__DEBUG_MODE", "true"),
             ("HTTP__BIND_ADDRESS", "127.0.0.1:3000"),
             ("HANDLER__RESPONSE__CACHE_DURATION", "10m"),
+            ("HANDLER__RESPONSE__UPSTREAM_MAX_AGE", "Override"),
+            ("HANDLER__OUTPUT__BACKGROUND", "#000000"),
+            ("POOL__SIZE", "4"),
+            ("LIMITS__MAX_SOURCE_BYTES", "33554432"),
+            ("LIMITS__MAX_OUTPUT_WIDTH", "4096"),
+            ("LIMITS__MAX_FRAMES", "100"),
             ("SOURCE__KIND", "WebFolder"),
             ("SOURCE__WEB_FOLDER__BASE_URL", "https://example.com"),
             ("SOURCE__PATH_PREFIX", "/assets"),
             ("SOURCE__NETWORK__CONFIG__TIMEOUT", "5s"),
+            ("SOURCE__NETWORK__CONFIG__CONNECT_TIMEOUT", "500ms"),
+            ("SOURCE__NETWORK__CONFIG__POOL_IDLE_TIMEOUT", "90s"),
+            ("SOURCE__NETWORK__CONFIG__POOL_MAX_IDLE_PER_HOST", "32"),
+            ("SOURCE__NETWORK__CONFIG__HTTP2_PRIOR_KNOWLEDGE", "true"),
+            ("SOURCE__NETWORK__CONFIG__PROXY", "http://proxy.internal:3128"),
+            ("SOURCE__NETWORK__CONFIG__USER_AGENT", "darkroom-rs"),
+            ("SOURCE__S3__BUCKET", "images"),
+            ("SOURCE__S3__REGION", "ap-south-1"),
+            ("SOURCE__S3__ENDPOINT", "http://localhost:9000"),
+            ("SOURCE__S3__ACCESS_KEY_ID", "access"),
+            ("SOURCE__S3__SECRET_ACCESS_KEY", "secret"),
+            ("SOURCE__FILE_SYSTEM__ROOT", "/var/lib/darkroom"),
         ];
         for (key, value) in &env_vars {
             env::set_var(key, value);
@@ -119,14 +455,241 @@ mod tests {
         assert_eq!(cfg.http.debug_mode, Some(true));
         assert_eq!(cfg.http.bind_address, SocketAddr::from_str("127.0.0.1:3000").unwrap());
         assert_eq!(cfg.handler.response.cache_duration, Duration::from_secs(600));
+        assert_eq!(cfg.handler.response.upstream_max_age, UpstreamMaxAge::Override);
+        assert_eq!(cfg.handler.output.background, Color([0, 0, 0]));
+        assert_eq!(cfg.handler.output.auto_formats, vec![Format::Avif, Format::WebP]);
+        assert_eq!(cfg.handler.output.avif_speed, 4);
+        assert_eq!(cfg.pool.size, 4);
+        assert_eq!(cfg.pool.queue_depth, 64);
+        assert_eq!(cfg.limits.max_source_bytes, Some(33554432));
+        assert_eq!(cfg.limits.max_width, 16384);
+        assert_eq!(cfg.limits.max_alloc, 536870912);
+        assert_eq!(cfg.limits.max_output_width, Some(4096));
+        assert_eq!(cfg.limits.max_output_height, None);
+        assert_eq!(cfg.limits.max_frames, 100);
+        assert_eq!(cfg.limits.max_animation_pixels, 67108864);
+
+        let source = cfg.source.unwrap();
+        assert_eq!(source.kind, SourceKind::WebFolder);
+        assert_eq!(source.web_folder.unwrap().base_url, Url::new("https://example.com").unwrap());
+        assert_eq!(source.path_prefix, Some("/assets".to_string()));
+        assert_eq!(source.network.config.timeout, Duration::from_secs(5));
+        assert_eq!(source.network.config.connect_timeout, Some(Duration::from_millis(500)));
+        assert_eq!(source.network.config.pool_idle_timeout, Some(Duration::from_secs(90)));
+        assert_eq!(source.network.config.pool_max_idle_per_host, Some(32));
+        assert_eq!(source.network.config.http2_prior_knowledge, Some(true));
+        assert_eq!(source.network.config.proxy, Some(Url::new("http://proxy.internal:3128").unwrap()));
+        assert_eq!(source.network.config.user_agent, Some("darkroom-rs".to_string()));
+        assert_eq!(source.cache_duration, None);
 
-        assert_eq!(cfg.source.kind, SourceKind::WebFolder);
-        assert_eq!(cfg.source.web_folder.unwrap().base_url, Url::new("https://example.com").unwrap());
-        assert_eq!(cfg.source.path_prefix, Some("/assets".to_string()));
-        assert_eq!(cfg.source.network.config.timeout, Duration::from_secs(5));
+        let s3 = source.s3.unwrap();
+        assert_eq!(s3.bucket, "images".to_string());
+        assert_eq!(s3.region, "ap-south-1".to_string());
+        assert_eq!(s3.endpoint, Some(Url::new("http://localhost:9000").unwrap()));
+        assert_eq!(s3.access_key_id, "access".to_string());
+        assert_eq!(s3.secret_access_key, "secret".to_string());
+        assert_eq!(s3.session_token, None);
+        assert_eq!(s3.force_path_style, None);
+
+        assert_eq!(source.file_system.unwrap().root, PathBuf::from("/var/lib/darkroom"));
 
         for (key, _) in &env_vars {
             env::remove_var(key);
         }
     }
+
+    #[test]
+    fn test_config_sources() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sources.yaml");
+        std::fs::write(&path, r#"
+log:
+  level: info
+http:
+  bind_address: 127.0.0.1:3000
+handler:
+  response:
+    cache_duration: 10m
+    stale_while_revalidate: 1m
+    stale_if_error: 1d
+  output:
+    auto_formats: [webp, png]
+    avif_speed: 8
+cache:
+  memory:
+    max_bytes: 268435456
+  disk:
+    path: /var/cache/darkroom
+    max_bytes: 10737418240
+  redis:
+    url: redis://127.0.0.1:6379
+    max_value_bytes: 5242880
+    compress: true
+admin:
+  token: secret
+  purge:
+    url: https://cdn.example.com/purge
+    headers:
+      fastly-key: cdn-secret
+sources:
+  - kind: S3
+    pattern: /avatars/*
+    cache_duration: 1d
+    s3:
+      bucket: avatars
+      region: ap-south-1
+      access_key_id: access
+      secret_access_key: secret
+    network:
+      config:
+        timeout: 2s
+  - kind: WebFolder
+    pattern: /static
+    path_prefix: /assets
+    retry:
+      max_attempts: 3
+      base_delay: 50ms
+      max_delay: 1s
+      jitter: true
+    circuit_breaker:
+      failure_ratio: 0.5
+      min_requests: 20
+      window: 10s
+      open_duration: 30s
+    stale_if_error:
+      max_bytes: 67108864
+      timeout: 500ms
+    web_folder:
+      base_url: https://example.com
+    network:
+      config:
+        timeout: 5s
+"#).unwrap();
+
+        let cfg = Config::parse(vec![File::from(path.as_path())]).unwrap();
+        let sources = &cfg.sources;
+
+        assert_eq!(cfg.cache.memory.unwrap().max_bytes, 268435456);
+        let disk = cfg.cache.disk.unwrap();
+        assert_eq!(disk.path, PathBuf::from("/var/cache/darkroom"));
+        assert_eq!(disk.max_bytes, 10737418240);
+        let redis = cfg.cache.redis.unwrap();
+        assert_eq!(redis.url, "redis://127.0.0.1:6379");
+        assert_eq!(redis.key_prefix, "darkroom:");
+        assert_eq!(redis.max_value_bytes, 5242880);
+        assert!(redis.compress);
+        assert_eq!(redis.timeout, Duration::from_millis(100));
+        let admin = cfg.admin.unwrap();
+        assert_eq!(admin.token, "secret");
+        assert_eq!(admin.purge.url, Url::new("https://cdn.example.com/purge").unwrap());
+        assert_eq!(admin.purge.headers.get("fastly-key"), Some(&"cdn-secret".to_string()));
+        assert_eq!(admin.purge.timeout, Duration::from_secs(5));
+        assert_eq!(sources.len(), 2);
+        assert_eq!(sources[0].kind, SourceKind::S3);
+        assert_eq!(sources[0].pattern, Some("/avatars/*".to_string()));
+        assert_eq!(sources[0].cache_duration, Some(Duration::from_secs(86400)));
+        assert_eq!(sources[0].network.config.timeout, Duration::from_secs(2));
+        assert_eq!(sources[0].s3.as_ref().unwrap().bucket, "avatars".to_string());
+        assert_eq!(sources[1].kind, SourceKind::WebFolder);
+        assert_eq!(sources[1].pattern, Some("/static".to_string()));
+        assert_eq!(sources[1].path_prefix, Some("/assets".to_string()));
+        assert_eq!(sources[1].cache_duration, None);
+        assert_eq!(sources[1].network.config.timeout, Duration::from_secs(5));
+
+        assert!(sources[0].retry.is_none());
+        let retry = sources[1].retry.as_ref().unwrap();
+        assert_eq!(retry.max_attempts, 3);
+        assert_eq!(retry.base_delay, Duration::from_millis(50));
+        assert_eq!(retry.max_delay, Duration::from_secs(1));
+        assert!(retry.jitter);
+        assert_eq!(retry.retryable_status_codes, vec![502, 503, 504]);
+        assert_eq!(retry.deadline, None);
+
+        assert!(sources[0].circuit_breaker.is_none());
+        let circuit_breaker = sources[1].circuit_breaker.as_ref().unwrap();
+        assert_eq!(circuit_breaker.failure_ratio, 0.5);
+        assert_eq!(circuit_breaker.min_requests, 20);
+        assert_eq!(circuit_breaker.window, Duration::from_secs(10));
+        assert_eq!(circuit_breaker.open_duration, Duration::from_secs(30));
+        assert_eq!(circuit_breaker.half_open_probes, 1);
+
+        assert!(sources[0].stale_if_error.is_none());
+        let stale_if_error = sources[1].stale_if_error.as_ref().unwrap();
+        assert_eq!(stale_if_error.max_bytes, 67108864);
+        assert_eq!(stale_if_error.timeout, Some(Duration::from_millis(500)));
+        assert_eq!(cfg.handler.response.stale_while_revalidate, Some(Duration::from_secs(60)));
+        assert_eq!(cfg.handler.response.stale_if_error, Some(Duration::from_secs(86400)));
+        assert_eq!(cfg.handler.output.auto_formats, vec![Format::WebP, Format::Png]);
+        assert_eq!(cfg.handler.output.avif_speed, 8);
+    }
+
+    #[test]
+    fn test_config_avif_speed() {
+        let dir = tempfile::tempdir().unwrap();
+        let parse = |speed: &str| {
+            let path = dir.path().join(format!("speed-{}.yaml", speed));
+            std::fs::write(&path, format!(r#"
+log:
+  level: info
+http:
+  bind_address: 127.0.0.1:3000
+handler:
+  response:
+    cache_duration: 10m
+  output:
+    avif_speed: {}
+"#, speed)).unwrap();
+            Config::parse(vec![File::from(path.as_path())])
+        };
+
+        assert_eq!(parse("1").unwrap().handler.output.avif_speed, 1);
+        assert_eq!(parse("10").unwrap().handler.output.avif_speed, 10);
+        for speed in ["0", "11"] {
+            let err = parse(speed).unwrap_err().to_string();
+            assert!(err.contains("avif_speed must be between 1 and 10"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_config_fallback() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fallback.yaml");
+        std::fs::write(&path, r#"
+log:
+  level: info
+http:
+  bind_address: 127.0.0.1:3000
+handler:
+  response:
+    cache_duration: 10m
+sources:
+  - kind: Fallback
+    fallback:
+      - kind: S3
+        name: bucket
+        s3:
+          bucket: images
+          region: ap-south-1
+          access_key_id: access
+          secret_access_key: secret
+      - kind: WebFolder
+        web_folder:
+          base_url: https://example.com
+        network:
+          config:
+            timeout: 1s
+"#).unwrap();
+
+        let cfg = Config::parse(vec![File::from(path.as_path())]).unwrap();
+        let source = &cfg.sources[0];
+        assert_eq!(source.kind, SourceKind::Fallback);
+        assert_eq!(source.name(), "fallback".to_string());
+
+        let fallback = source.fallback.as_ref().unwrap();
+        assert_eq!(fallback.len(), 2);
+        assert_eq!(fallback[0].name(), "bucket".to_string());
+        assert_eq!(fallback[0].network.config.timeout, Duration::from_secs(5));
+        assert_eq!(fallback[1].name(), "webfolder".to_string());
+        assert_eq!(fallback[1].network.config.timeout, Duration::from_secs(1));
+    }
 }
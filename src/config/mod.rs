@@ -1,5 +1,17 @@
+#[allow(clippy::module_inception)]
 pub(super) mod config;
 pub(crate) mod url;
+pub(crate) mod color;
 
 pub use config::Config;
+pub use config::CircuitBreaker;
+pub use config::Limits;
+pub use config::NetworkConfig;
+pub use config::Output;
+pub use config::Pool;
+pub use config::Retry;
+pub use config::Source;
 pub use config::SourceKind;
+pub use config::StaleIfError;
+pub use config::UpstreamMaxAge;
+pub use config::Webhook;
@@ -8,6 +8,22 @@ pub struct Url(ImplUrl);
 
 impl Url {
     pub fn new(url: &str) -> Result<Url, ParseError> { Ok(Url(ImplUrl::parse(url)?)) }
+
+    /// Appends `prefix` and `path` to this URL's path, keeping its port and query. Each path
+    /// segment is percent-encoded, so `path` must be given in its decoded form.
+    pub fn join_path(&self, prefix: Option<&str>, path: &str) -> Result<Url, ParseError> {
+        let mut url = self.0.clone();
+        url.set_fragment(None);
+        url.path_segments_mut()
+            .map_err(|_| ParseError::RelativeUrlWithCannotBeABaseBase)?
+            .pop_if_empty()
+            .extend(
+                prefix.unwrap_or("").split('/')
+                    .chain(path.split('/'))
+                    .filter(|s| !s.is_empty())
+            );
+        Ok(Url(url))
+    }
 }
 
 impl Deref for Url {
@@ -21,7 +37,7 @@ impl DerefMut for Url {
 
 impl TryFrom<W<&String>> for Url {
     type Error = ParseError;
-    fn try_from(val: W<&String>) -> Result<Url, ParseError> { Ok(Url::new(&val.0)?) }
+    fn try_from(val: W<&String>) -> Result<Url, ParseError> { Url::new(val.0) }
 }
 
 impl<'de> Deserialize<'de> for Url {
@@ -30,7 +46,7 @@ impl<'de> Deserialize<'de> for Url {
         D: serde::Deserializer<'de>,
     {
         let url = String::deserialize(deserializer)?;
-        Ok(W(&url).try_into().map_err(serde::de::Error::custom)?)
+        W(&url).try_into().map_err(serde::de::Error::custom)
     }
 }
 
@@ -45,6 +61,39 @@ mod tests {
         assert_eq!(url.host_str(), Some("example.com"));
     }
 
+    #[test]
+    fn test_join_path() {
+        let testcases = vec![
+            ("http://origin", None, "a.jpg", "http://origin/a.jpg"),
+            ("http://origin/", None, "/a.jpg", "http://origin/a.jpg"),
+            ("http://origin:8080/images/v2", None, "a.jpg", "http://origin:8080/images/v2/a.jpg"),
+            ("http://origin:8080/images/v2/", None, "a.jpg", "http://origin:8080/images/v2/a.jpg"),
+            ("http://origin/images", Some("/assets"), "a.jpg", "http://origin/images/assets/a.jpg"),
+            ("http://origin/images", Some("assets/"), "/a.jpg", "http://origin/images/assets/a.jpg"),
+            ("http://origin/images", Some("/assets/cats/"), "a.jpg", "http://origin/images/assets/cats/a.jpg"),
+            ("http://origin", None, "cat photo#1?.jpg", "http://origin/cat%20photo%231%3F.jpg"),
+            ("http://origin", None, "100%.jpg", "http://origin/100%25.jpg"),
+            ("http://origin/images", None, "../a.jpg", "http://origin/images/a.jpg"),
+            ("http://origin", None, "ümlaut.jpg", "http://origin/%C3%BCmlaut.jpg"),
+            ("https://origin/images?token=abc#top", Some("v2"), "a.jpg", "https://origin/images/v2/a.jpg?token=abc"),
+        ];
+
+        for (base, prefix, path, expected) in testcases {
+            assert_eq!(
+                Url::new(base).unwrap().join_path(prefix, path).unwrap().as_str(),
+                expected,
+            );
+        }
+    }
+
+    #[test]
+    fn test_join_path_cannot_be_a_base() {
+        assert_eq!(
+            Url::new("mailto:someone@example.com").unwrap().join_path(None, "a.jpg"),
+            Err(ParseError::RelativeUrlWithCannotBeABaseBase)
+        );
+    }
+
     #[test]
     fn test_set() {
         let mut url = Url::new("https://example.com").unwrap();
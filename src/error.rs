@@ -1,4 +1,4 @@
-use crate::processor;
+use crate::{processor, storage};
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -10,4 +10,6 @@ pub enum Error {
     Config(#[from] config::ConfigError),
     #[error(transparent)]
     Image(#[from] processor::error::Error),
+    #[error(transparent)]
+    Storage(#[from] storage::errors::Error),
 }
@@ -0,0 +1,228 @@
+use std::io::Cursor;
+use std::num::NonZeroU16;
+use axum::http::StatusCode;
+use image::codecs::gif::GifDecoder;
+use image::error::DecodingError;
+use image::{
+    AnimationDecoder, Delay, DynamicImage, Frame, Frames, ImageDecoder, ImageError, ImageFormat, RgbImage, RgbaImage,
+};
+use image_webp::{LoopCount, WebPDecoder};
+use crate::config::Limits;
+use crate::handler::error::Error;
+use crate::handler::limits;
+use crate::processor::Image;
+
+/// Frames of a source image, a single one for still images. Animations keep the delay of
+/// each frame and their loop count.
+pub struct Animation {
+    pub frames: Vec<Image>,
+    pub delays: Vec<Delay>,
+    /// Loop count stored in the source, where 0 loops forever. GIFs without one play once.
+    pub loop_count: Option<u16>,
+}
+
+impl Animation {
+    pub fn still(image: Image) -> Self {
+        Animation { frames: vec![image], delays: vec![], loop_count: None }
+    }
+
+    pub fn is_animated(&self) -> bool { self.frames.len() > 1 }
+}
+
+/// Whether `format` can be encoded with more than one frame.
+pub fn supports_animation(format: ImageFormat) -> bool {
+    matches!(format, ImageFormat::Gif | ImageFormat::WebP)
+}
+
+/// Decodes the frames of GIFs and animated WebPs, or only the one selected with `frame`.
+/// Returns `None` for other sources, which are decoded as still images.
+pub fn decode(
+    content: &[u8],
+    format: Option<ImageFormat>,
+    limits: &Limits,
+    frame: Option<NonZeroU16>,
+) -> Result<Option<Animation>, Error> {
+    let decode_error = |err| limits::decode_error(limits, err);
+
+    let (frames, format, loop_count) = match format {
+        Some(format @ ImageFormat::Gif) => {
+            let mut decoder = GifDecoder::new(Cursor::new(content)).map_err(decode_error)?;
+            decoder.set_limits(limits::decoder(limits)).map_err(decode_error)?;
+            (decoder.into_frames(), format, gif_loop_count(content))
+        }
+        Some(format @ ImageFormat::WebP) => {
+            let mut decoder = WebPDecoder::new(Cursor::new(content)).map_err(webp_error).map_err(decode_error)?;
+            if !decoder.is_animated() {
+                return Ok(None);
+            }
+            let (width, height) = decoder.dimensions();
+            limits::decoder(limits).check_dimensions(width, height).map_err(decode_error)?;
+            decoder.set_memory_limit(usize::try_from(limits.max_alloc).unwrap_or(usize::MAX));
+
+            let loop_count = match decoder.loop_count() {
+                LoopCount::Forever => 0,
+                LoopCount::Times(count) => count.get(),
+            };
+            (webp_frames(decoder), format, Some(loop_count))
+        }
+        _ => return Ok(None),
+    };
+
+    let (frames, delays) = collect(frames, limits, frame)?;
+    Ok(Some(Animation {
+        frames: frames.into_iter().map(|frame| Image::format(frame, format)).collect(),
+        delays,
+        loop_count,
+    }))
+}
+
+/// Iterates over the frames of an animated WebP. `image` has an iterator for these as well,
+/// but it does not stop after the last frame.
+fn webp_frames<'a>(mut decoder: WebPDecoder<Cursor<&'a [u8]>>) -> Frames<'a> {
+    let (width, height) = decoder.dimensions();
+
+    let frames = (0..decoder.num_frames()).map(move |_| {
+        let (buffer, delay) = match decoder.has_alpha() {
+            true => {
+                let mut buffer = RgbaImage::new(width, height);
+                let delay = decoder.read_frame(&mut buffer).map_err(webp_error)?;
+                (buffer, delay)
+            }
+            false => {
+                let mut buffer = RgbImage::new(width, height);
+                let delay = decoder.read_frame(&mut buffer).map_err(webp_error)?;
+                (DynamicImage::ImageRgb8(buffer).into_rgba8(), delay)
+            }
+        };
+        Ok(Frame::from_parts(buffer, 0, 0, Delay::from_numer_denom_ms(delay, 1)))
+    });
+
+    Frames::new(Box::new(frames))
+}
+
+fn webp_error(err: image_webp::DecodingError) -> ImageError {
+    ImageError::Decoding(DecodingError::new(ImageFormat::WebP.into(), err))
+}
+
+/// Collects decoded frames within the frame and pixel limits, skipping to `frame` if given.
+fn collect(
+    frames: Frames,
+    limits: &Limits,
+    frame: Option<NonZeroU16>,
+) -> Result<(Vec<DynamicImage>, Vec<Delay>), Error> {
+    let (mut images, mut delays) = (vec![], vec![]);
+    let (mut count, mut pixels) = (0u32, 0u64);
+
+    for decoded in frames {
+        if count == limits.max_frames {
+            return Err(Error::TooManyFrames { limit: limits.max_frames });
+        }
+        count += 1;
+
+        let decoded = decoded.map_err(|err| limits::decode_error(limits, err))?;
+        let (width, height) = decoded.buffer().dimensions();
+        pixels += u64::from(width) * u64::from(height);
+        if pixels > limits.max_animation_pixels {
+            return Err(Error::AnimationTooLarge { limit: limits.max_animation_pixels });
+        }
+
+        match frame {
+            Some(frame) if count < u32::from(frame.get()) => continue,
+            Some(_) => return Ok((vec![DynamicImage::ImageRgba8(decoded.into_buffer())], vec![])),
+            None => {
+                delays.push(decoded.delay());
+                images.push(DynamicImage::ImageRgba8(decoded.into_buffer()));
+            }
+        }
+    }
+
+    match frame {
+        Some(frame) => Err(Error::FrameNotFound { frame: frame.get(), frames: count }),
+        None if images.is_empty() => Err(StatusCode::INTERNAL_SERVER_ERROR.into()),
+        None => Ok((images, delays)),
+    }
+}
+
+/// Reads the loop count from the NETSCAPE2.0 extension of a GIF, which `image` does not expose.
+fn gif_loop_count(content: &[u8]) -> Option<u16> {
+    let start = content.windows(11).position(|w| w == b"NETSCAPE2.0")? + 11;
+    match content.get(start..start + 4)? {
+        [3, 1, lo, hi] => Some(u16::from_le_bytes([*lo, *hi])),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::codecs::gif::{GifEncoder, Repeat};
+    use image::Rgba;
+    use super::*;
+
+    /// A GIF of `frames` 4x4 frames of increasing brightness, 100ms each.
+    fn gif(frames: u8, repeat: Option<Repeat>) -> Vec<u8> {
+        let mut content = vec![];
+        {
+            let mut encoder = GifEncoder::new(&mut content);
+            if let Some(repeat) = repeat {
+                encoder.set_repeat(repeat).unwrap();
+            }
+            encoder.encode_frames((0..frames).map(|i| {
+                let buffer = RgbaImage::from_pixel(4, 4, Rgba([i * 50, i * 50, i * 50, 255]));
+                Frame::from_parts(buffer, 0, 0, Delay::from_numer_denom_ms(100, 1))
+            })).unwrap();
+        }
+        content
+    }
+
+    #[test]
+    fn test_decode() {
+        let limits = Limits::default();
+        let animation = decode(&gif(3, Some(Repeat::Finite(2))), Some(ImageFormat::Gif), &limits, None)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(animation.frames.len(), 3);
+        assert_eq!(animation.frames[2].format, Some(ImageFormat::Gif));
+        assert_eq!(animation.frames[2].to_rgba8().get_pixel(0, 0), &Rgba([100, 100, 100, 255]));
+        assert_eq!(animation.delays, vec![Delay::from_numer_denom_ms(100, 1); 3]);
+        assert_eq!(animation.loop_count, Some(2));
+
+        let animation = decode(&gif(2, None), Some(ImageFormat::Gif), &limits, None).unwrap().unwrap();
+        assert_eq!(animation.loop_count, None);
+
+        assert!(decode(b"not an animation", Some(ImageFormat::Png), &limits, None).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_decode_frame() {
+        let content = gif(3, Some(Repeat::Infinite));
+        let decode = |frame| decode(&content, Some(ImageFormat::Gif), &Limits::default(), NonZeroU16::new(frame));
+
+        let animation = decode(2).unwrap().unwrap();
+        assert!(!animation.is_animated());
+        assert_eq!(animation.frames[0].to_rgba8().get_pixel(0, 0), &Rgba([50, 50, 50, 255]));
+
+        assert_eq!(decode(4).err(), Some(Error::FrameNotFound { frame: 4, frames: 3 }));
+    }
+
+    #[test]
+    fn test_decode_limits() {
+        let content = gif(3, None);
+        let decode = |limits: Limits| decode(&content, Some(ImageFormat::Gif), &limits, None).err();
+
+        assert_eq!(decode(Limits { max_frames: 3, ..Limits::default() }), None);
+        assert_eq!(decode(Limits { max_frames: 2, ..Limits::default() }), Some(Error::TooManyFrames { limit: 2 }));
+        assert_eq!(decode(Limits { max_animation_pixels: 48, ..Limits::default() }), None);
+        assert_eq!(
+            decode(Limits { max_animation_pixels: 47, ..Limits::default() }),
+            Some(Error::AnimationTooLarge { limit: 47 })
+        );
+    }
+
+    #[test]
+    fn test_gif_loop_count() {
+        assert_eq!(gif_loop_count(&gif(2, Some(Repeat::Infinite))), Some(0));
+        assert_eq!(gif_loop_count(&gif(2, Some(Repeat::Finite(7)))), Some(7));
+        assert_eq!(gif_loop_count(&gif(2, None)), None);
+    }
+}
@@ -0,0 +1,66 @@
+use std::time::SystemTime;
+use axum::http::header::{IF_MODIFIED_SINCE, IF_NONE_MATCH};
+use axum::http::HeaderMap;
+use httpdate::HttpDate;
+
+/// Evaluates `If-None-Match`, or `If-Modified-Since` in its absence, against the validators of
+/// the representation that would be served.
+pub fn is_not_modified(headers: &HeaderMap, etag: Option<&str>, last_modified: Option<SystemTime>) -> bool {
+    let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+
+    if let Some(if_none_match) = header(IF_NONE_MATCH) {
+        return if_none_match.split(',').map(str::trim).any(|tag| {
+            tag == "*" || etag.is_some_and(|etag| opaque(tag) == opaque(etag))
+        });
+    }
+
+    match (header(IF_MODIFIED_SINCE).and_then(|v| httpdate::parse_http_date(v).ok()), last_modified) {
+        // HTTP dates have a resolution of one second.
+        (Some(since), Some(modified)) => SystemTime::from(HttpDate::from(modified)) <= since,
+        _ => false,
+    }
+}
+
+/// Strips the weak indicator, as `If-None-Match` uses the weak comparison.
+fn opaque(tag: &str) -> &str {
+    tag.strip_prefix("W/").unwrap_or(tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use axum::http::HeaderValue;
+    use super::*;
+
+    fn headers(entries: &[(&'static str, &'static str)]) -> HeaderMap {
+        entries.iter().map(|(k, v)| (k.parse().unwrap(), HeaderValue::from_static(v))).collect()
+    }
+
+    #[test]
+    fn test_is_not_modified() {
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1369353600) + Duration::from_millis(500);
+        let testcases = vec![
+            (vec![], false),
+            (vec![("If-None-Match", "\"abc\"")], true),
+            (vec![("If-None-Match", "W/\"abc\"")], true),
+            (vec![("If-None-Match", "\"xyz\", \"abc\"")], true),
+            (vec![("If-None-Match", "*")], true),
+            (vec![("If-None-Match", "\"xyz\"")], false),
+            (vec![("If-Modified-Since", "Fri, 24 May 2013 00:00:00 GMT")], true),
+            (vec![("If-Modified-Since", "Thu, 23 May 2013 23:59:59 GMT")], false),
+            (vec![("If-Modified-Since", "not a date")], false),
+            // If-None-Match takes precedence.
+            (vec![("If-None-Match", "\"xyz\""), ("If-Modified-Since", "Fri, 24 May 2013 00:00:00 GMT")], false),
+        ];
+
+        for (entries, expected) in testcases {
+            assert_eq!(is_not_modified(&headers(&entries), Some("\"abc\""), Some(modified)), expected, "{:?}", entries);
+        }
+    }
+
+    #[test]
+    fn test_is_not_modified_without_validators() {
+        let headers = headers(&[("If-None-Match", "\"abc\""), ("If-Modified-Since", "Fri, 24 May 2013 00:00:00 GMT")]);
+        assert!(!is_not_modified(&headers, None, None));
+    }
+}
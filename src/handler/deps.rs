@@ -1,6 +1,13 @@
 use std::sync::Arc;
 use std::time::Duration;
 use prometheus::Registry;
+use crate::cache::Cache;
+use crate::coalesce::Coalescer;
+use crate::config::{Limits, Output, UpstreamMaxAge};
+use crate::handler::error::Error;
+use crate::handler::negotiate::Negotiator;
+use crate::handler::render::Rendered;
+use crate::pool::BlockingPool;
 use crate::processor::chainer::ChainProcessor;
 use crate::storage;
 
@@ -9,5 +16,14 @@ pub struct Dependencies {
     pub registry: Arc<Registry>,
     pub storage: Arc<dyn storage::Getter + Send + Sync>,
     pub processor: Arc<ChainProcessor>,
+    pub coalescer: Arc<Coalescer<String, Result<Rendered, Error>>>,
+    pub pool: Arc<BlockingPool>,
+    pub limits: Limits,
+    pub output: Output,
+    pub negotiator: Arc<Negotiator>,
+    pub cache: Arc<dyn Cache + Send + Sync>,
     pub cache_time: Duration,
+    pub upstream_max_age: UpstreamMaxAge,
+    pub stale_while_revalidate: Option<Duration>,
+    pub stale_if_error: Option<Duration>,
 }
@@ -0,0 +1,262 @@
+use std::io::Cursor;
+use image::codecs::avif::AvifEncoder;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::error::{EncodingError, ImageFormatHint};
+use image::{DynamicImage, Frame, ImageError, ImageFormat};
+use crate::handler::animation::Animation;
+use crate::handler::query::{AutoFeature, ProcessParams};
+
+/// Quality `auto=compress` encodes lossy formats with when `q` is not given.
+const AUTO_QUALITY: u8 = 75;
+/// Quality of the `image` crate's JPEG encoder.
+const DEFAULT_JPEG_QUALITY: u8 = 75;
+/// Quality of the `image` crate's AVIF encoder.
+const DEFAULT_AVIF_QUALITY: u8 = 80;
+
+/// Encoder settings for an output format, derived from `q` and `auto=compress`. Without
+/// either, images are encoded with the `image` crate's defaults.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Settings {
+    pub format: ImageFormat,
+    pub quality: Option<u8>,
+    pub compress: bool,
+    pub avif_speed: u8,
+}
+
+impl Settings {
+    pub fn new(format: ImageFormat, params: &ProcessParams, avif_speed: u8) -> Self {
+        Settings {
+            format,
+            quality: params.quality.map(|q| q.0),
+            compress: params.auto_features.as_ref().is_some_and(|f| f.contains(&AutoFeature::Compress)),
+            avif_speed,
+        }
+    }
+
+    fn lossy_quality(&self) -> Option<u8> {
+        self.quality.or(self.compress.then_some(AUTO_QUALITY))
+    }
+
+    /// Describes the compression applied, for the `X-Darkroom-Compression` header.
+    pub fn describe(&self) -> Option<String> {
+        match self.format {
+            ImageFormat::Jpeg => {
+                let quality = self.lossy_quality().unwrap_or(DEFAULT_JPEG_QUALITY);
+                Some(match self.compress {
+                    true => format!("q={}; optimized", quality),
+                    false => format!("q={}", quality),
+                })
+            }
+            ImageFormat::Png if self.compress => Some("level=best; filter=adaptive".to_string()),
+            ImageFormat::WebP => Some(match self.lossy_quality() {
+                Some(quality) => format!("q={}", quality),
+                None => "lossless".to_string(),
+            }),
+            ImageFormat::Avif => {
+                let quality = self.lossy_quality().unwrap_or(DEFAULT_AVIF_QUALITY);
+                Some(format!("q={}; speed={}", quality, self.avif_speed))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn encode(&self, image: &DynamicImage) -> Result<Vec<u8>, ImageError> {
+        let mut buffer = Cursor::new(Vec::new());
+
+        match (self.format, self.lossy_quality()) {
+            (ImageFormat::Jpeg, Some(quality)) => {
+                let rgb = image.to_rgb8();
+                let (width, height) = (dimension(rgb.width())?, dimension(rgb.height())?);
+
+                let mut encoder = jpeg_encoder::Encoder::new(buffer.get_mut(), quality);
+                encoder.set_optimized_huffman_tables(self.compress);
+                encoder.encode(&rgb, width, height, jpeg_encoder::ColorType::Rgb)
+                    .map_err(|err| encoding_error(ImageFormat::Jpeg, err))?;
+            }
+            (ImageFormat::Png, _) if self.compress => {
+                let encoder = PngEncoder::new_with_quality(&mut buffer, CompressionType::Best, FilterType::Adaptive);
+                image.write_with_encoder(encoder)?;
+            }
+            (ImageFormat::WebP, Some(quality)) => {
+                // `Encoder::encode` panics on images libwebp refuses, e.g. over 16383 pixels wide.
+                let quality = quality as f32;
+                let encoded = match image.color().has_alpha() {
+                    true => {
+                        let rgba = image.to_rgba8();
+                        webp::Encoder::from_rgba(&rgba, rgba.width(), rgba.height()).encode_simple(false, quality)
+                    }
+                    false => {
+                        let rgb = image.to_rgb8();
+                        webp::Encoder::from_rgb(&rgb, rgb.width(), rgb.height()).encode_simple(false, quality)
+                    }
+                }.map_err(|err| encoding_error(ImageFormat::WebP, format!("{:?}", err)))?;
+                buffer.get_mut().extend_from_slice(&encoded);
+            }
+            (ImageFormat::Avif, quality) => {
+                let quality = quality.unwrap_or(DEFAULT_AVIF_QUALITY);
+                image.write_with_encoder(AvifEncoder::new_with_speed_quality(&mut buffer, self.avif_speed, quality))?;
+            }
+            (format, _) => image.write_to(&mut buffer, format)?,
+        }
+
+        Ok(buffer.into_inner())
+    }
+
+    /// Encodes every frame of an animation as an animated GIF or WebP, keeping the delays and
+    /// the loop count. Other formats get the first frame only.
+    pub fn encode_animation(&self, animation: &Animation) -> Result<Vec<u8>, ImageError> {
+        let frames = animation.frames.iter().map(|frame| frame.to_rgba8()).zip(animation.delays.iter().copied());
+
+        match self.format {
+            ImageFormat::Gif => {
+                let mut buffer = vec![];
+                {
+                    let mut encoder = GifEncoder::new(&mut buffer);
+                    match animation.loop_count {
+                        Some(0) => encoder.set_repeat(Repeat::Infinite)?,
+                        Some(count) => encoder.set_repeat(Repeat::Finite(count))?,
+                        None => {}
+                    }
+                    encoder.encode_frames(frames.map(|(buffer, delay)| Frame::from_parts(buffer, 0, 0, delay)))?;
+                }
+                Ok(buffer)
+            }
+            ImageFormat::WebP => {
+                let frames: Vec<_> = frames.collect();
+                let Some((first, _)) = frames.first() else { return Ok(vec![]) };
+
+                let mut config = webp::WebPConfig::new()
+                    .map_err(|_| encoding_error(ImageFormat::WebP, "invalid WebP config"))?;
+                match self.lossy_quality() {
+                    Some(quality) => config.quality = quality as f32,
+                    None => config.lossless = 1,
+                }
+
+                let mut encoder = webp::AnimEncoder::new(first.width(), first.height(), &config);
+                // A GIF without a loop count plays once, which is a count of 1 in WebP.
+                encoder.set_loop_count(i32::from(animation.loop_count.unwrap_or(1)));
+                let mut timestamp = 0;
+                for (buffer, delay) in &frames {
+                    encoder.add_frame(webp::AnimFrame::from_rgba(buffer, buffer.width(), buffer.height(), timestamp));
+                    let (numer, denom) = delay.numer_denom_ms();
+                    timestamp += (numer / denom.max(1)) as i32;
+                }
+
+                let encoded = encoder.try_encode()
+                    .map_err(|err| encoding_error(ImageFormat::WebP, format!("{:?}", err)))?;
+                Ok(encoded.to_vec())
+            }
+            _ => match animation.frames.first() {
+                Some(frame) => self.encode(frame),
+                None => Ok(vec![]),
+            },
+        }
+    }
+}
+
+fn dimension(value: u32) -> Result<u16, ImageError> {
+    u16::try_from(value).map_err(|err| encoding_error(ImageFormat::Jpeg, err))
+}
+
+fn encoding_error(format: ImageFormat, err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> ImageError {
+    ImageError::Encoding(EncodingError::new(ImageFormatHint::Exact(format), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::extract::Query;
+    use image::{Rgb, RgbImage};
+    use crate::config::Limits;
+    use crate::handler::animation;
+    use super::*;
+
+    fn params(query: &str) -> ProcessParams {
+        let uri = format!("/image.jpg?{}", query).parse().unwrap();
+        Query::<ProcessParams>::try_from_uri(&uri).unwrap().0
+    }
+
+    /// A noisy image, which compresses differently at each quality.
+    fn image() -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(64, 64, |x, y| {
+            Rgb([(x * 7 + y * 13) as u8, (x * y) as u8, (x ^ y) as u8 * 4])
+        }))
+    }
+
+    #[test]
+    fn test_describe() {
+        let testcases = vec![
+            (ImageFormat::Jpeg, "", Some("q=75")),
+            (ImageFormat::Jpeg, "q=40", Some("q=40")),
+            (ImageFormat::Jpeg, "auto=compress", Some("q=75; optimized")),
+            (ImageFormat::Jpeg, "auto=compress&q=60", Some("q=60; optimized")),
+            (ImageFormat::Png, "", None),
+            (ImageFormat::Png, "q=40", None),
+            (ImageFormat::Png, "auto=compress", Some("level=best; filter=adaptive")),
+            (ImageFormat::WebP, "", Some("lossless")),
+            (ImageFormat::WebP, "auto=compress", Some("q=75")),
+            (ImageFormat::WebP, "q=90", Some("q=90")),
+            (ImageFormat::Avif, "", Some("q=80; speed=6")),
+            (ImageFormat::Avif, "auto=compress", Some("q=75; speed=6")),
+            (ImageFormat::Avif, "q=50", Some("q=50; speed=6")),
+            (ImageFormat::Gif, "auto=compress", None),
+        ];
+
+        for (format, query, expected) in testcases {
+            let settings = Settings::new(format, &params(query), 6);
+            assert_eq!(settings.describe().as_deref(), expected, "{:?} {}", format, query);
+        }
+    }
+
+    #[test]
+    fn test_encode() {
+        let image = image();
+        let size = |format, query: &str| {
+            let encoded = Settings::new(format, &params(query), 10).encode(&image).unwrap();
+            if format == ImageFormat::Avif {
+                assert_eq!(&encoded[4..12], b"ftypavif", "{}", query);
+            } else {
+                assert_eq!(image::guess_format(&encoded).unwrap(), format, "{}", query);
+            }
+            encoded.len()
+        };
+
+        assert!(size(ImageFormat::Jpeg, "q=20") < size(ImageFormat::Jpeg, "q=90"));
+        assert!(size(ImageFormat::Jpeg, "auto=compress") < size(ImageFormat::Jpeg, ""));
+        assert!(size(ImageFormat::WebP, "q=20") < size(ImageFormat::WebP, "q=90"));
+        assert!(size(ImageFormat::WebP, "auto=compress") < size(ImageFormat::WebP, ""));
+        assert!(size(ImageFormat::Png, "auto=compress") <= size(ImageFormat::Png, ""));
+        assert!(size(ImageFormat::Avif, "q=20") < size(ImageFormat::Avif, "q=90"));
+    }
+
+    #[test]
+    fn test_encode_webp_too_wide() {
+        let image = DynamicImage::ImageRgb8(RgbImage::new(16384, 1));
+        let err = Settings::new(ImageFormat::WebP, &params("q=75"), 10).encode(&image).unwrap_err();
+        assert!(matches!(err, ImageError::Encoding(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn test_encode_animation() {
+        let mut frames: Vec<_> = (0..3u8)
+            .map(|i| crate::processor::Image::new(DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+                4, 4, image::Rgba([i * 50, 0, 0, 255]),
+            ))))
+            .collect();
+        let delay = image::Delay::from_numer_denom_ms(80, 1);
+
+        for loop_count in [None, Some(0), Some(3)] {
+            let animation = Animation { frames, delays: vec![delay; 3], loop_count };
+            for format in [ImageFormat::Gif, ImageFormat::WebP] {
+                let encoded = Settings::new(format, &params(""), 10).encode_animation(&animation).unwrap();
+                let decoded = animation::decode(&encoded, Some(format), &Limits::default(), None).unwrap().unwrap();
+
+                assert_eq!(decoded.frames.len(), 3, "{:?}", format);
+                assert_eq!(decoded.delays, vec![delay; 3], "{:?}", format);
+                let expected = if format == ImageFormat::WebP { loop_count.or(Some(1)) } else { loop_count };
+                assert_eq!(decoded.loop_count, expected, "{:?}", format);
+            }
+            frames = animation.frames;
+        }
+    }
+}
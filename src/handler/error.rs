@@ -0,0 +1,92 @@
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Serialize;
+use crate::storage;
+
+/// Error returned by the image handler. Requests over one of the configured limits or for
+/// sources that cannot be decoded get a JSON body saying why, other errors only a status code.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum Error {
+    #[error("{0}")]
+    Status(StatusCode),
+    #[error("source image is larger than the limit of {limit} bytes")]
+    SourceTooLarge { limit: u64 },
+    #[error("source image is larger than the limit of {max_width}x{max_height} pixels")]
+    DimensionsTooLarge { max_width: u32, max_height: u32 },
+    #[error("decoding the source image needs more than the limit of {limit} bytes")]
+    AllocationTooLarge { limit: u64 },
+    #[error("{param}={value} is over the limit of {limit}")]
+    OutputTooLarge { param: &'static str, value: u16, limit: u16 },
+    #[error("source image format is not supported: {format}")]
+    UnsupportedFormat { format: String },
+    #[error("source animation has more frames than the limit of {limit}")]
+    TooManyFrames { limit: u32 },
+    #[error("source animation has more pixels than the limit of {limit}, summed over its frames")]
+    AnimationTooLarge { limit: u64 },
+    #[error("frame={frame} is out of range, the source has {frames} frames")]
+    FrameNotFound { frame: u16, frames: u32 },
+}
+
+#[derive(Serialize)]
+struct Body {
+    error: &'static str,
+    message: String,
+}
+
+impl Error {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Error::Status(status) => *status,
+            Error::SourceTooLarge { .. }
+            | Error::DimensionsTooLarge { .. }
+            | Error::AllocationTooLarge { .. }
+            | Error::TooManyFrames { .. }
+            | Error::AnimationTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            Error::OutputTooLarge { .. } | Error::FrameNotFound { .. } => StatusCode::BAD_REQUEST,
+            Error::UnsupportedFormat { .. } => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            Error::Status(_) => "",
+            Error::SourceTooLarge { .. } => "source_too_large",
+            Error::DimensionsTooLarge { .. } => "dimensions_too_large",
+            Error::AllocationTooLarge { .. } => "allocation_too_large",
+            Error::OutputTooLarge { .. } => "output_too_large",
+            Error::UnsupportedFormat { .. } => "unsupported_format",
+            Error::TooManyFrames { .. } => "too_many_frames",
+            Error::AnimationTooLarge { .. } => "animation_too_large",
+            Error::FrameNotFound { .. } => "frame_not_found",
+        }
+    }
+}
+
+/// Storage errors only keep their status code, except for sources over the size limit.
+impl From<storage::errors::Error> for Error {
+    fn from(err: storage::errors::Error) -> Self {
+        match err {
+            storage::errors::Error::TooLarge { limit } => Error::SourceTooLarge { limit },
+            err => err.status_code().into(),
+        }
+    }
+}
+
+impl From<StatusCode> for Error {
+    fn from(status: StatusCode) -> Self {
+        Error::Status(status)
+    }
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        match self {
+            Error::Status(status) => status.into_response(),
+            err => {
+                let body = Body { error: err.code(), message: err.to_string() };
+                (err.status_code(), Json(body)).into_response()
+            }
+        }
+    }
+}
@@ -1,71 +1,178 @@
-use std::io::Cursor;
 use std::sync::Arc;
+use axum::body::Body;
 use axum::extract::{Extension, Path, Query};
-use axum::http::StatusCode;
-use axum::response::IntoResponse;
-use image::ImageFormat;
-use image::io::Reader as ImageReader;
+use axum::http::header::{IF_MODIFIED_SINCE, IF_NONE_MATCH, RANGE};
+use axum::http::HeaderMap;
 use crate::handler::Dependencies;
-use crate::storage::GetRequest;
-use crate::handler::query::ProcessParams;
+use image::ImageFormat;
+use crate::storage::{self, ByteRange, GetRequest, GetRequestOptions, Metadata};
+use crate::handler::conditional::is_not_modified;
+use crate::handler::query::{AutoFeature, ProcessParams};
+use crate::handler::render::{etag, render};
 use crate::handler::response::Response;
-use crate::processor::Image;
+use crate::surrogate;
+use crate::handler::encode::Settings;
+use crate::handler::error::Error;
+use crate::handler::limits;
+use crate::cache::Entry;
 
 pub async fn image(
     Extension(deps): Extension<Arc<Dependencies>>,
     Path(path): Path<String>,
-    Query(params): Query<ProcessParams>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let res = deps.storage.get(GetRequest { path, options: None })
-        .await
-        .map_err(|e| e.status_code())?;
+    Query(mut params): Query<ProcessParams>,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    let surrogate_keys = surrogate::keys(&path);
 
     if params.is_noop() {
-        return Ok((StatusCode::OK, Response {
-            image: (res.content, None),
-            cache_time: deps.cache_time,
-        }));
+        let res = match deps.storage.get_stream(GetRequest { path, options: options(&headers) }).await {
+            Ok(res) => res,
+            Err(storage::errors::Error::NotModified { metadata, .. }) => {
+                return Ok(Response {
+                    image: (Body::empty(), None),
+                    cache_time: deps.cache_time,
+                    backend: None,
+                    etag: metadata.etag.clone(),
+                    metadata: Some(metadata),
+                    upstream_max_age: deps.upstream_max_age,
+                    content_range: None,
+                    not_modified: true,
+                    stale_while_revalidate: deps.stale_while_revalidate,
+                    stale_if_error: deps.stale_if_error,
+                    stale: false,
+                    surrogate_keys,
+                    compression: None,
+                });
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let etag = res.metadata.as_ref().and_then(|m| m.etag.clone());
+        let last_modified = res.metadata.as_ref().and_then(|m| m.last_modified);
+        let not_modified = is_not_modified(&headers, etag.as_deref(), last_modified);
+
+        return Ok(Response {
+            image: (if not_modified { Body::empty() } else { Body::from_stream(res.content) }, None),
+            cache_time: res.cache_time.unwrap_or(deps.cache_time),
+            backend: res.backend,
+            metadata: res.metadata,
+            upstream_max_age: deps.upstream_max_age,
+            content_range: res.content_range,
+            etag,
+            not_modified,
+            stale_while_revalidate: deps.stale_while_revalidate,
+            stale_if_error: deps.stale_if_error,
+            stale: res.stale,
+            surrogate_keys,
+            compression: None,
+        });
+    }
+
+    limits::check_output(&deps.limits, &params)?;
+
+    if params.format.is_none() && params.auto_features.as_ref().is_some_and(|f| f.contains(&AutoFeature::Format)) {
+        params.format = deps.negotiator.negotiate(&headers);
     }
 
-    let reader = ImageReader::new(Cursor::new(res.content))
-        .with_guessed_format()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let key = format!("{}?{}", path, params.cache_key());
+    if let Some(entry) = deps.cache.get(&key).await {
+        let not_modified = is_not_modified(&headers, entry.etag.as_deref(), entry.last_modified);
+        let format = entry.content_type.as_deref().and_then(ImageFormat::from_mime_type);
+        let compression = format.and_then(|f| Settings::new(f, &params, deps.output.avif_speed).describe());
+
+        return Ok(Response {
+            image: (if not_modified { Body::empty() } else { entry.content.into() }, format),
+            cache_time: entry.cache_time,
+            backend: entry.backend,
+            metadata: Some(Metadata {
+                last_modified: entry.last_modified,
+                cache_control: entry.cache_control,
+                ..Metadata::default()
+            }),
+            upstream_max_age: deps.upstream_max_age,
+            content_range: None,
+            etag: entry.etag,
+            not_modified,
+            stale_while_revalidate: deps.stale_while_revalidate,
+            stale_if_error: deps.stale_if_error,
+            stale: false,
+            surrogate_keys,
+            compression,
+        });
+    }
 
-    let format = reader.format();
+    // Sources over `max_source_bytes` are refused by the storage, see `Router::getter`.
+    let mut res = deps.storage.get(GetRequest { path, options: None }).await?;
 
-    let decoded = reader
-        .decode()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let etag = etag(&res, &params);
+    let last_modified = res.metadata.as_ref().and_then(|m| m.last_modified);
+    let not_modified = is_not_modified(&headers, Some(&etag), last_modified);
+    let cache_time = res.cache_time.unwrap_or(deps.cache_time);
 
-    let mut image = if format.is_none() {
-        Image::new(decoded)
+    let (image, compression) = if not_modified {
+        ((Body::empty(), None), None)
     } else {
-        Image::format(decoded, format.unwrap())
+        // Different sources can share an ETag, e.g. files of the same size and mtime.
+        let content = std::mem::take(&mut res.content);
+        let rendered = deps.coalescer.run(format!("{}|{}", key, etag), render(deps.clone(), content, params)).await?;
+
+        // Renders of stale copies are not kept, so that the source is retried.
+        if !res.stale {
+            deps.cache.set(&key, Entry {
+                content: rendered.content.clone(),
+                content_type: rendered.format.map(|f| f.to_mime_type().to_string()),
+                etag: Some(etag.clone()),
+                last_modified,
+                backend: res.backend.clone(),
+                cache_control: res.metadata.as_ref().and_then(|m| m.cache_control),
+                cache_time: storage::cache_time(cache_time, res.metadata.as_ref(), deps.upstream_max_age),
+            }).await;
+        }
+
+        ((rendered.content.into(), rendered.format), rendered.compression)
     };
 
-    if deps.processor.process(&mut image, params).is_err() {
-        return Err(StatusCode::INTERNAL_SERVER_ERROR);
-    }
+    Ok(Response {
+        image,
+        cache_time,
+        backend: res.backend,
+        metadata: res.metadata,
+        upstream_max_age: deps.upstream_max_age,
+        content_range: None,
+        etag: Some(etag),
+        not_modified,
+        stale_while_revalidate: deps.stale_while_revalidate,
+        stale_if_error: deps.stale_if_error,
+        stale: res.stale,
+        surrogate_keys,
+        compression,
+    })
+}
 
-    let mut buffer = Cursor::new(Vec::new());
-    image.write_to(&mut buffer, format.unwrap_or_else(|| ImageFormat::Jpeg))
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+/// Builds the options forwarded to the storage from the range and conditional request headers.
+fn options(headers: &HeaderMap) -> Option<GetRequestOptions> {
+    let header = |name| headers.get(name).and_then(|v| v.to_str().ok()).map(|v| v.to_string());
 
-    Ok((StatusCode::OK, Response {
-        image: (buffer.into_inner(), image.format),
-        cache_time: deps.cache_time,
-    }))
+    let options = GetRequestOptions {
+        range: header(RANGE).map(ByteRange::new),
+        if_none_match: header(IF_NONE_MATCH),
+        if_modified_since: header(IF_MODIFIED_SINCE),
+    };
+    Some(options).filter(|o| *o != GetRequestOptions::default())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::Cursor;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use async_trait::async_trait;
     use mockall::predicate::*;
     use tower::ServiceExt;
     use http_body_util::BodyExt;
     use std::time::Duration;
     use axum::body::Body;
-    use axum::http::Request;
+    use axum::http::{Request, StatusCode};
     use axum::routing::get;
     use opentelemetry::metrics::MeterProvider;
     use prometheus::Registry;
@@ -73,6 +180,17 @@ mod tests {
     use crate::storage::GetResponse;
     use crate::storage::getter::MockGetter;
     use crate::processor::chainer::ChainProcessor;
+    use crate::coalesce::Coalescer;
+    use crate::cache::memory::MemoryCache;
+    use crate::cache::noop::NoCache;
+    use crate::storage::coalesce::CoalescingGetter;
+    use crate::config::{Limits, Output, Pool, UpstreamMaxAge};
+    use crate::handler::Negotiator;
+    use crate::handler::query::Format;
+    use crate::handler::animation::{self, Animation};
+    use crate::pool::BlockingPool;
+    use image::codecs::gif::{GifEncoder, Repeat};
+    use image::{Delay, Frame, Rgba, RgbaImage};
 
 
     fn deps(mock: MockGetter) -> Arc<Dependencies> {
@@ -84,8 +202,17 @@ mod tests {
         Arc::new(Dependencies {
             registry: Arc::new(Registry::new()),
             storage: Arc::new(mock),
-            processor: Arc::new(ChainProcessor::new(meter)),
+            processor: Arc::new(ChainProcessor::new(meter.clone())),
+            coalescer: Arc::new(Coalescer::new("processor", &meter)),
+            pool: Arc::new(BlockingPool::new(&Pool { size: 2, queue_depth: 8 }, &meter)),
+            limits: Limits::default(),
+            output: Output { avif_speed: 10, ..Output::default() },
+            negotiator: Arc::new(Negotiator::new(vec![Format::Avif, Format::WebP], &meter)),
+            cache: Arc::new(NoCache),
             cache_time: Duration::from_secs(300),
+            upstream_max_age: UpstreamMaxAge::Cap,
+            stale_while_revalidate: None,
+            stale_if_error: None,
         })
     }
 
@@ -98,16 +225,13 @@ mod tests {
     #[tokio::test]
     async fn noop() {
         let mut mock = MockGetter::new();
-        mock.expect_get()
+        mock.expect_get_stream()
             .with(eq(GetRequest {
                 path: "test.jpg".to_string(),
                 options: None,
             }))
             .times(1)
-            .returning(|_| Ok(GetResponse {
-                content: vec![1, 2, 3],
-                metadata: None,
-            }));
+            .returning(|_| Ok(GetResponse { content: vec![1, 2, 3], ..GetResponse::default() }.into_stream()));
 
         let res = router(deps(mock))
             .oneshot(
@@ -126,7 +250,7 @@ mod tests {
     #[tokio::test]
     async fn storage_get_error() {
         let mut mock = MockGetter::new();
-        mock.expect_get()
+        mock.expect_get_stream()
             .with(eq(GetRequest {
                 path: "test.jpg".to_string(),
                 options: None,
@@ -148,4 +272,497 @@ mod tests {
 
         assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
     }
+
+    struct Slow {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl storage::Getter for Slow {
+        async fn get(&self, _: GetRequest) -> Result<GetResponse, storage::errors::Error> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+
+            Ok(GetResponse { content: png(), ..GetResponse::default() })
+        }
+    }
+
+    #[tokio::test]
+    async fn coalesced_requests() {
+        let origin = Arc::new(Slow { calls: AtomicUsize::new(0) });
+        let mut deps = Arc::unwrap_or_clone(deps(MockGetter::new()));
+        deps.storage = Arc::new(CoalescingGetter::new(
+            origin.clone(),
+            &opentelemetry::global::meter_provider().meter("test-meter"),
+        ));
+        let router = router(Arc::new(deps));
+
+        let req = |uri: &str| router.clone().oneshot(
+            Request::builder().uri(uri).body(Body::empty()).unwrap()
+        );
+        let (a1, a2, b) = tokio::join!(req("/test.png?w=2"), req("/test.png?w=2"), req("/test.png?w=3"));
+
+        // All renders of the same source share a single fetch.
+        assert_eq!(a1.unwrap().status(), StatusCode::OK);
+        assert_eq!(a2.unwrap().status(), StatusCode::OK);
+        assert_eq!(b.unwrap().status(), StatusCode::OK);
+        assert_eq!(origin.calls.load(Ordering::SeqCst), 1);
+    }
+
+    /// Returns a white image for `white.png` and a black one otherwise, both with the same ETag.
+    struct SameEtag;
+
+    #[async_trait]
+    impl storage::Getter for SameEtag {
+        async fn get(&self, req: GetRequest) -> Result<GetResponse, storage::errors::Error> {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+
+            let color = if req.path == "white.png" { Rgba([255, 255, 255, 255]) } else { Rgba([0, 0, 0, 255]) };
+            let mut content = Cursor::new(Vec::new());
+            RgbaImage::from_pixel(4, 4, color).write_to(&mut content, image::ImageFormat::Png).unwrap();
+
+            Ok(GetResponse {
+                content: content.into_inner(),
+                metadata: Some(Metadata { etag: Some("W/\"10-20\"".to_string()), ..Metadata::default() }),
+                ..GetResponse::default()
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn coalesced_renders_same_etag() {
+        let mut deps = Arc::unwrap_or_clone(deps(MockGetter::new()));
+        deps.storage = Arc::new(SameEtag);
+        let router = router(Arc::new(deps));
+
+        let req = |uri: &str| router.clone().oneshot(
+            Request::builder().uri(uri).body(Body::empty()).unwrap()
+        );
+        let (white, black) = tokio::join!(req("/white.png?w=2"), req("/black.png?w=2"));
+
+        for (res, expected) in [(white, [255, 255, 255]), (black, [0, 0, 0])] {
+            let body = res.unwrap().into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(image::load_from_memory(&body).unwrap().to_rgb8().get_pixel(0, 0).0, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn range() {
+        let mut mock = MockGetter::new();
+        mock.expect_get_stream()
+            .with(eq(GetRequest {
+                path: "test.jpg".to_string(),
+                options: Some(GetRequestOptions {
+                    range: Some(ByteRange::new("bytes=0-1")),
+                    ..GetRequestOptions::default()
+                }),
+            }))
+            .times(1)
+            .returning(|_| Ok(GetResponse {
+                content: vec![1, 2],
+                content_range: Some("bytes 0-1/3".to_string()),
+                ..GetResponse::default()
+            }.into_stream()));
+
+        let res = router(deps(mock))
+            .oneshot(
+                Request::builder().uri("/test.jpg")
+                    .header("Range", "bytes=0-1")
+                    .body(Body::empty())
+                    .unwrap()
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(res.headers().get("Content-Range").unwrap(), "bytes 0-1/3");
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body.len(), 2);
+    }
+
+    fn png() -> Vec<u8> {
+        let mut content = Cursor::new(Vec::new());
+        image::DynamicImage::new_rgb8(4, 4)
+            .write_to(&mut content, image::ImageFormat::Png)
+            .unwrap();
+        content.into_inner()
+    }
+
+    #[tokio::test]
+    async fn etag_not_modified() {
+        let mut mock = MockGetter::new();
+        mock.expect_get()
+            .times(2)
+            .returning(|_| Ok(GetResponse { content: png(), ..GetResponse::default() }));
+        let router = router(deps(mock));
+
+        let res = router.clone()
+            .oneshot(Request::builder().uri("/test.png?w=2").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let etag = res.headers().get("ETag").unwrap().clone();
+
+        let res = router
+            .oneshot(
+                Request::builder().uri("/test.png?w=2")
+                    .header("If-None-Match", etag.clone())
+                    .body(Body::empty())
+                    .unwrap()
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(res.headers().get("ETag"), Some(&etag));
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert!(body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn noop_forwards_conditional_headers() {
+        let mut mock = MockGetter::new();
+        mock.expect_get_stream()
+            .with(eq(GetRequest {
+                path: "test.jpg".to_string(),
+                options: Some(GetRequestOptions {
+                    if_none_match: Some("\"v1\"".to_string()),
+                    ..GetRequestOptions::default()
+                }),
+            }))
+            .times(1)
+            .returning(|req| Err(storage::errors::Error::NotModified {
+                path: req.path,
+                metadata: Metadata {
+                    etag: Some("\"v1\"".to_string()),
+                    cache_control: Some(Duration::from_secs(60)),
+                    ..Metadata::default()
+                },
+            }));
+
+        let res = router(deps(mock))
+            .oneshot(
+                Request::builder().uri("/test.jpg")
+                    .header("If-None-Match", "\"v1\"")
+                    .body(Body::empty())
+                    .unwrap()
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(res.headers().get("ETag").unwrap(), "\"v1\"");
+        assert_eq!(res.headers().get("Cache-Control").unwrap(), "public, max-age=60");
+        assert_eq!(res.headers().get("Vary").unwrap(), "Accept");
+    }
+
+    #[tokio::test]
+    async fn cached_renders() {
+        let mut mock = MockGetter::new();
+        mock.expect_get()
+            .times(1)
+            .returning(|_| Ok(GetResponse {
+                content: png(),
+                metadata: Some(Metadata { cache_control: Some(Duration::from_secs(60)), ..Metadata::default() }),
+                ..GetResponse::default()
+            }));
+        let mut deps = Arc::unwrap_or_clone(deps(mock));
+        deps.cache = Arc::new(MemoryCache::new(
+            1024 * 1024,
+            &opentelemetry::global::meter_provider().meter("test-meter"),
+        ));
+        let router = router(Arc::new(deps));
+
+        // The hit is fresh only for what is left of the origin's max-age.
+        let mut bodies = vec![];
+        for max_age in [60, 59] {
+            let res = router.clone()
+                .oneshot(Request::builder().uri("/test.png?w=2").body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(res.headers().get("Content-Type").unwrap(), "image/png");
+            assert_eq!(res.headers().get("Cache-Control").unwrap(), &format!("public, max-age={}", max_age));
+            bodies.push(res.into_body().collect().await.unwrap().to_bytes());
+        }
+        assert_eq!(bodies[0], bodies[1]);
+    }
+
+    #[tokio::test]
+    async fn limits() {
+        let testcases = vec![
+            ("/test.png?w=2", Limits { max_width: 2, ..Limits::default() }, 413, "dimensions_too_large"),
+            ("/test.png?w=2", Limits { max_alloc: 16, ..Limits::default() }, 413, "allocation_too_large"),
+            ("/test.png?w=200", Limits { max_output_width: Some(100), ..Limits::default() }, 400, "output_too_large"),
+            ("/test.png?h=200", Limits { max_output_height: Some(100), ..Limits::default() }, 400, "output_too_large"),
+        ];
+
+        for (uri, limits, status, error) in testcases {
+            let mut mock = MockGetter::new();
+            mock.expect_get().returning(|_| Ok(GetResponse { content: png(), ..GetResponse::default() }));
+            let mut deps = Arc::unwrap_or_clone(deps(mock));
+            deps.limits = limits;
+
+            let res = router(Arc::new(deps))
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(res.status(), status, "{}", error);
+
+            let body = res.into_body().collect().await.unwrap().to_bytes();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["error"], error);
+            assert!(body["message"].as_str().unwrap().contains("limit"));
+        }
+    }
+
+    #[tokio::test]
+    async fn source_too_large() {
+        let mut mock = MockGetter::new();
+        mock.expect_get().returning(|_| Err(storage::errors::Error::TooLarge { limit: 10 }));
+
+        let res = router(deps(mock))
+            .oneshot(Request::builder().uri("/test.png?w=2").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"], "source_too_large");
+        assert_eq!(body["message"], "source image is larger than the limit of 10 bytes");
+    }
+
+    #[tokio::test]
+    async fn output_format() {
+        let mut mock = MockGetter::new();
+        mock.expect_get().returning(|_| {
+            let mut content = Cursor::new(Vec::new());
+            image::DynamicImage::new_rgba8(4, 4)
+                .write_to(&mut content, image::ImageFormat::Png)
+                .unwrap();
+            Ok(GetResponse { content: content.into_inner(), ..GetResponse::default() })
+        });
+        let router = router(deps(mock));
+
+        let testcases = vec![
+            ("/test.png?w=2", "image/png"),
+            ("/test.png?fm=webp", "image/webp"),
+            ("/test.png?fm=gif", "image/gif"),
+            ("/test.png?w=2&fm=jpg", "image/jpeg"),
+        ];
+        for (uri, content_type) in testcases {
+            let res = router.clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK, "{}", uri);
+            assert_eq!(res.headers().get("Content-Type").unwrap(), content_type, "{}", uri);
+
+            let body = res.into_body().collect().await.unwrap().to_bytes();
+            let format = image::guess_format(&body).unwrap();
+            assert_eq!(format.to_mime_type(), content_type, "{}", uri);
+
+            // Transparent pixels are flattened onto the white default background.
+            if format == image::ImageFormat::Jpeg {
+                let decoded = image::load_from_memory(&body).unwrap().to_rgb8();
+                assert!(decoded.get_pixel(0, 0).0.iter().all(|&c| c > 250));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn auto_format() {
+        let mut mock = MockGetter::new();
+        mock.expect_get().returning(|_| Ok(GetResponse { content: png(), ..GetResponse::default() }));
+        let router = router(deps(mock));
+
+        let testcases = vec![
+            ("/test.png?auto=format", Some("image/avif,image/webp,*/*"), "image/avif"),
+            ("/test.png?auto=format", Some("image/webp,*/*"), "image/webp"),
+            ("/test.png?auto=format", Some("*/*"), "image/png"),
+            ("/test.png?auto=format", None, "image/png"),
+            ("/test.png?auto=format&fm=jpg", Some("image/webp,*/*"), "image/jpeg"),
+        ];
+
+        let mut etags = vec![];
+        for (uri, accept, content_type) in testcases {
+            let mut req = Request::builder().uri(uri);
+            if let Some(accept) = accept {
+                req = req.header("Accept", accept);
+            }
+            let res = router.clone().oneshot(req.body(Body::empty()).unwrap()).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK, "{:?}", accept);
+            assert_eq!(res.headers().get("Content-Type").unwrap(), content_type, "{:?}", accept);
+            assert_eq!(res.headers().get("Vary").unwrap(), "Accept");
+            etags.push(res.headers().get("ETag").unwrap().clone());
+        }
+
+        // Each negotiated format is a separate representation.
+        assert_ne!(etags[0], etags[1]);
+        assert_ne!(etags[1], etags[2]);
+        assert_eq!(etags[2], etags[3]);
+    }
+
+    #[tokio::test]
+    async fn compression() {
+        let mut mock = MockGetter::new();
+        mock.expect_get().returning(|_| Ok(GetResponse { content: png(), ..GetResponse::default() }));
+        let router = router(deps(mock));
+
+        let testcases = vec![
+            ("/test.png?fm=jpg&q=40", StatusCode::OK, Some("q=40")),
+            ("/test.png?fm=webp&auto=compress", StatusCode::OK, Some("q=75")),
+            ("/test.png?auto=compress", StatusCode::OK, Some("level=best; filter=adaptive")),
+            ("/test.png?w=2", StatusCode::OK, None),
+            ("/test.png?q=0", StatusCode::BAD_REQUEST, None),
+        ];
+        for (uri, status, compression) in testcases {
+            let res = router.clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(res.status(), status, "{}", uri);
+            assert_eq!(
+                res.headers().get("X-Darkroom-Compression").map(|v| v.to_str().unwrap()),
+                compression,
+                "{}",
+                uri
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn avif() {
+        let res = avif_router()
+            .oneshot(Request::builder().uri("/test.png?fm=avif&q=60").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers().get("Content-Type").unwrap(), "image/avif");
+        assert_eq!(res.headers().get("X-Darkroom-Compression").unwrap(), "q=60; speed=10");
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[4..12], b"ftypavif");
+    }
+
+    /// Serves `.avif` paths as 4x4 AVIFs and other paths as 4x4 PNGs.
+    fn avif_router() -> axum::Router {
+        let mut mock = MockGetter::new();
+        mock.expect_get().returning(|uri| {
+            let format = if uri.path.ends_with(".avif") { image::ImageFormat::Avif } else { image::ImageFormat::Png };
+            let mut content = Cursor::new(Vec::new());
+            image::DynamicImage::new_rgb8(4, 4).write_to(&mut content, format).unwrap();
+            Ok(GetResponse { content: content.into_inner(), ..GetResponse::default() })
+        });
+        router(deps(mock))
+    }
+
+    #[cfg(feature = "avif-decode")]
+    #[tokio::test]
+    async fn avif_source() {
+        let res = avif_router()
+            .oneshot(Request::builder().uri("/test.avif?w=2&fm=png").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers().get("Content-Type").unwrap(), "image/png");
+
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        let image = image::load_from_memory(&body).unwrap();
+        assert_eq!((image.width(), image.height()), (2, 2));
+    }
+
+    /// Decoding AVIF needs dav1d, so builds without the `avif-decode` feature refuse AVIF sources.
+    #[cfg(not(feature = "avif-decode"))]
+    #[tokio::test]
+    async fn avif_source_unsupported() {
+        let res = avif_router()
+            .oneshot(Request::builder().uri("/test.avif?w=2&fm=png").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"], "unsupported_format");
+    }
+
+    /// An animated GIF of three 8x8 frames, 100ms each, looping forever.
+    fn gif() -> Vec<u8> {
+        let mut content = vec![];
+        {
+            let mut encoder = GifEncoder::new(&mut content);
+            encoder.set_repeat(Repeat::Infinite).unwrap();
+            encoder.encode_frames((0..3u8).map(|i| {
+                let buffer = RgbaImage::from_pixel(8, 8, Rgba([i * 100, 0, 0, 255]));
+                Frame::from_parts(buffer, 0, 0, Delay::from_numer_denom_ms(100, 1))
+            })).unwrap();
+        }
+        content
+    }
+
+    #[tokio::test]
+    async fn animation() {
+        let mut mock = MockGetter::new();
+        mock.expect_get().returning(|_| Ok(GetResponse { content: gif(), ..GetResponse::default() }));
+        let router = router(deps(mock));
+
+        let testcases = vec![
+            ("/test.gif?w=4", "image/gif", 3),
+            ("/test.gif?w=4&fm=webp", "image/webp", 3),
+            ("/test.gif?w=4&fm=jpg", "image/jpeg", 1),
+            ("/test.gif?w=4&frame=2", "image/gif", 1),
+            ("/test.gif?frame=3&fm=png", "image/png", 1),
+        ];
+        for (uri, content_type, frames) in testcases {
+            let res = router.clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK, "{}", uri);
+            assert_eq!(res.headers().get("Content-Type").unwrap(), content_type, "{}", uri);
+
+            let body = res.into_body().collect().await.unwrap().to_bytes();
+            let format = ImageFormat::from_mime_type(content_type).unwrap();
+            let decoded = match animation::decode(&body, Some(format), &Limits::default(), None).unwrap() {
+                Some(animation) => animation,
+                None => Animation::still(image::load_from_memory(&body).unwrap().into()),
+            };
+            assert_eq!(decoded.frames.len(), frames, "{}", uri);
+            assert_eq!(decoded.frames[0].width(), if uri.contains("w=4") { 4 } else { 8 }, "{}", uri);
+            if frames > 1 {
+                assert_eq!(decoded.delays, vec![Delay::from_numer_denom_ms(100, 1); 3], "{}", uri);
+                assert_eq!(decoded.loop_count, Some(0), "{}", uri);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn animation_limits() {
+        let testcases = vec![
+            ("/test.gif?w=4", Limits { max_frames: 2, ..Limits::default() }, 413, "too_many_frames"),
+            ("/test.gif?w=4", Limits { max_animation_pixels: 128, ..Limits::default() }, 413, "animation_too_large"),
+            ("/test.gif?frame=4", Limits::default(), 400, "frame_not_found"),
+            ("/test.png?frame=2", Limits::default(), 400, "frame_not_found"),
+        ];
+
+        for (uri, limits, status, error) in testcases {
+            let mut mock = MockGetter::new();
+            mock.expect_get().returning(|req| {
+                let content = if req.path.ends_with(".gif") { gif() } else { png() };
+                Ok(GetResponse { content, ..GetResponse::default() })
+            });
+            let mut deps = Arc::unwrap_or_clone(deps(mock));
+            deps.limits = limits;
+
+            let res = router(Arc::new(deps))
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(res.status(), status, "{}", uri);
+
+            let body = res.into_body().collect().await.unwrap().to_bytes();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["error"], error, "{}", uri);
+        }
+    }
 }
@@ -0,0 +1,85 @@
+use image::error::{LimitErrorKind, ImageError};
+use axum::http::StatusCode;
+use crate::config::Limits;
+use crate::handler::error::Error;
+use crate::handler::query::ProcessParams;
+
+/// Rejects requests for outputs larger than the configured maximum before fetching the source.
+pub fn check_output(limits: &Limits, params: &ProcessParams) -> Result<(), Error> {
+    let checks = [
+        ("w", params.width, limits.max_output_width),
+        ("h", params.height, limits.max_output_height),
+    ];
+
+    for (param, value, limit) in checks {
+        if let (Some(value), Some(limit)) = (value, limit) {
+            if value > limit {
+                return Err(Error::OutputTooLarge { param, value, limit });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Limits enforced by the decoder, which checks the dimensions in the image header before
+/// allocating anything.
+pub fn decoder(limits: &Limits) -> image::io::Limits {
+    let mut decoder = image::io::Limits::default();
+    decoder.max_image_width = Some(limits.max_width);
+    decoder.max_image_height = Some(limits.max_height);
+    decoder.max_alloc = Some(limits.max_alloc);
+    decoder
+}
+
+/// Maps decoder errors, telling sources over the limits and in unsupported formats apart.
+pub fn decode_error(limits: &Limits, err: ImageError) -> Error {
+    let err = match err {
+        ImageError::Limits(err) => err,
+        ImageError::Unsupported(err) => return Error::UnsupportedFormat { format: format!("{}", err.format_hint()) },
+        _ => return StatusCode::INTERNAL_SERVER_ERROR.into(),
+    };
+
+    match err.kind() {
+        LimitErrorKind::DimensionError => Error::DimensionsTooLarge {
+            max_width: limits.max_width,
+            max_height: limits.max_height,
+        },
+        LimitErrorKind::InsufficientMemory => Error::AllocationTooLarge { limit: limits.max_alloc },
+        _ => StatusCode::INTERNAL_SERVER_ERROR.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use image::io::Reader as ImageReader;
+    use super::*;
+
+    /// A PNG whose header claims `width`x`height` pixels, with no image data behind it.
+    fn bomb(width: u32, height: u32) -> Vec<u8> {
+        let mut ihdr = b"IHDR".to_vec();
+        ihdr.extend(width.to_be_bytes());
+        ihdr.extend(height.to_be_bytes());
+        ihdr.extend([8, 2, 0, 0, 0]);
+        let mut crc = flate2::Crc::new();
+        crc.update(&ihdr);
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png.extend(13u32.to_be_bytes());
+        png.extend(ihdr);
+        png.extend(crc.sum().to_be_bytes());
+        png
+    }
+
+    #[test]
+    fn test_decode_bomb() {
+        let limits = Limits::default();
+        let mut reader = ImageReader::new(Cursor::new(bomb(50000, 50000)))
+            .with_guessed_format()
+            .unwrap();
+        reader.limits(decoder(&limits));
+
+        let err = decode_error(&limits, reader.decode().unwrap_err());
+        assert_eq!(err, Error::DimensionsTooLarge { max_width: 16384, max_height: 16384 });
+    }
+}
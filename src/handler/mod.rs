@@ -2,6 +2,16 @@ mod image;
 mod deps;
 pub mod query;
 mod response;
+mod render;
+mod animation;
+mod conditional;
+mod error;
+mod limits;
+mod encode;
+mod negotiate;
+mod purge;
 
 pub use image::image;
 pub use deps::Dependencies;
+pub use negotiate::Negotiator;
+pub use purge::{purge, Admin};
@@ -0,0 +1,86 @@
+use axum::http::header::ACCEPT;
+use axum::http::HeaderMap;
+use image::ImageFormat;
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::{Counter, Meter};
+use crate::handler::query::Format;
+
+/// Picks the output format for `auto=format` requests: the first format of the preference
+/// order that the client lists in `Accept`, or the original format if it lists none of them.
+pub struct Negotiator {
+    preference: Vec<Format>,
+    counter: Counter<u64>,
+}
+
+impl Negotiator {
+    pub fn new(preference: Vec<Format>, meter: &Meter) -> Self {
+        Self {
+            preference,
+            counter: meter.u64_counter("negotiated_formats")
+                .with_description("Number of auto=format requests by the format negotiated for them")
+                .init(),
+        }
+    }
+
+    pub fn negotiate(&self, headers: &HeaderMap) -> Option<Format> {
+        let accept = headers.get_all(ACCEPT).iter()
+            .filter_map(|v| v.to_str().ok())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let format = self.preference.iter().copied()
+            .find(|&format| accepts(&accept, ImageFormat::from(format).to_mime_type()));
+
+        let label = format.map_or("original".to_string(), |f| format!("{:?}", f).to_lowercase());
+        self.counter.add(1, &[KeyValue::new("format", label)]);
+        format
+    }
+}
+
+/// Whether `accept` lists `mime` explicitly with a non-zero quality. Wildcards are ignored,
+/// as clients sending `*/*` rarely mean they can decode every image format.
+fn accepts(accept: &str, mime: &str) -> bool {
+    accept.split(',').any(|range| {
+        let mut parts = range.split(';').map(str::trim);
+        let matches = parts.next().is_some_and(|media_type| media_type.eq_ignore_ascii_case(mime));
+
+        let quality = parts
+            .filter_map(|param| param.split_once('='))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+            .map_or(1.0, |(_, q)| q.trim().parse::<f32>().unwrap_or(0.0));
+
+        matches && quality > 0.0
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+    use opentelemetry::metrics::MeterProvider;
+    use super::*;
+
+    #[test]
+    fn test_negotiate() {
+        let negotiator = Negotiator::new(
+            vec![Format::Avif, Format::WebP],
+            &opentelemetry::global::meter_provider().meter("test-meter"),
+        );
+        let testcases = vec![
+            (Some("image/avif,image/webp,image/apng,image/*,*/*;q=0.8"), Some(Format::Avif)),
+            (Some("image/webp,*/*"), Some(Format::WebP)),
+            (Some("image/avif;q=0,image/webp;q=0.5"), Some(Format::WebP)),
+            (Some("IMAGE/AVIF"), Some(Format::Avif)),
+            (Some("image/*,*/*"), None),
+            (Some("image/png"), None),
+            (None, None),
+        ];
+
+        for (accept, expected) in testcases {
+            let mut headers = HeaderMap::new();
+            if let Some(accept) = accept {
+                headers.insert(ACCEPT, HeaderValue::from_static(accept));
+            }
+            assert_eq!(negotiator.negotiate(&headers), expected, "{:?}", accept);
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use std::sync::Arc;
+use axum::body::Bytes;
+use axum::extract::Extension;
+use axum::http::header::AUTHORIZATION;
+use axum::http::{HeaderMap, StatusCode};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use crate::cache::Cache;
+use crate::cdn::Purger;
+use crate::surrogate;
+use crate::storage;
+
+pub struct Admin {
+    pub token: String,
+    pub purger: Arc<dyn Purger + Send + Sync>,
+    /// Caches of rendered images and of originals, purged before the CDN so that it cannot
+    /// refetch what it purges from them.
+    pub cache: Arc<dyn Cache + Send + Sync>,
+    pub storage: Arc<dyn storage::Getter + Send + Sync>,
+}
+
+impl Admin {
+    fn is_authorized(&self, headers: &HeaderMap) -> bool {
+        let Some(token) = headers.get(AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer ")) else { return false };
+
+        // Compares in constant time, so that the token cannot be guessed from response times.
+        token.len() == self.token.len()
+            && token.bytes().zip(self.token.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PurgeRequest {
+    /// Path of an original, or a prefix of the paths to purge.
+    path: String,
+}
+
+#[derive(Serialize)]
+pub struct PurgeResponse {
+    surrogate_keys: Vec<String>,
+}
+
+/// Purges every rendered variant of an original, or of all originals under a prefix, from
+/// the caches of the proxy and the CDN.
+pub async fn purge(
+    Extension(admin): Extension<Arc<Admin>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<PurgeResponse>, StatusCode> {
+    if !admin.is_authorized(&headers) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    let req: PurgeRequest = serde_json::from_slice(&body).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let key = surrogate::key(&req.path);
+    if key.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    // Rendered variants are keyed by their path and parameters, see `image`.
+    admin.cache.purge(&|cached| {
+        cached.rsplit_once('?').is_some_and(|(path, _)| surrogate::covers(&key, path))
+    }).await;
+    admin.storage.purge(&key).await;
+
+    let keys = vec![key];
+    admin.purger.purge(&keys).await.map_err(|_| StatusCode::BAD_GATEWAY)?;
+
+    Ok(Json(PurgeResponse { surrogate_keys: keys }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use std::sync::Mutex;
+    use axum::body::Body;
+    use axum::http::Request;
+    use axum::routing::post;
+    use http_body_util::BodyExt;
+    use tokio::net::TcpListener;
+    use opentelemetry::metrics::MeterProvider;
+    use tower::ServiceExt;
+    use crate::cache::Entry;
+    use crate::cache::memory::MemoryCache;
+    use crate::cdn::WebhookPurger;
+    use crate::config::{UpstreamMaxAge, Webhook};
+    use crate::config::url::Url;
+    use crate::storage::cache::CachingGetter;
+    use crate::storage::getter::MockGetter;
+    use super::*;
+
+    /// Stands in for the CDN's purge endpoint, recording the bodies it receives and answering
+    /// with `status`.
+    async fn cdn(status: StatusCode) -> (Url, Arc<Mutex<Vec<(Option<String>, String)>>>) {
+        let received = Arc::new(Mutex::new(vec![]));
+        let r = received.clone();
+
+        let app = axum::Router::new().route("/purge", post(move |headers: HeaderMap, body: String| async move {
+            let key = headers.get("Fastly-Key").map(|v| v.to_str().unwrap().to_string());
+            r.lock().unwrap().push((key, body));
+            status
+        }));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        (Url::new(&format!("http://{}/purge", addr)).unwrap(), received)
+    }
+
+    fn cache() -> Arc<MemoryCache> {
+        Arc::new(MemoryCache::new(1024, &opentelemetry::global::meter_provider().meter("test-meter")))
+    }
+
+    /// Keeps rendered images and originals in `cache`, as the proxy does with a disk cache.
+    fn router(url: Url, cache: Arc<MemoryCache>) -> axum::Router {
+        let purger = WebhookPurger::new(Webhook {
+            url,
+            headers: [("Fastly-Key".to_string(), "cdn-secret".to_string())].into(),
+            timeout: Duration::from_secs(1),
+        }).unwrap();
+        let mut origin = MockGetter::new();
+        origin.expect_purge().return_const(());
+
+        let admin = Admin {
+            token: "secret".to_string(),
+            purger: Arc::new(purger),
+            cache: cache.clone(),
+            storage: Arc::new(CachingGetter::new(
+                Arc::new(origin),
+                cache,
+                Duration::from_secs(60),
+                UpstreamMaxAge::Cap,
+            )),
+        };
+
+        axum::Router::new()
+            .route("/_admin/purge", post(purge))
+            .layer(Extension(Arc::new(admin)))
+    }
+
+    fn entry() -> Entry {
+        Entry {
+            content: Bytes::from_static(&[1]),
+            content_type: None,
+            etag: None,
+            last_modified: None,
+            backend: None,
+            cache_control: None,
+            cache_time: Duration::from_secs(60),
+        }
+    }
+
+    fn request(token: &str, body: &str) -> Request<Body> {
+        Request::post("/_admin/purge")
+            .header(AUTHORIZATION, format!("Bearer {}", token))
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_purge() {
+        let (url, received) = cdn(StatusCode::OK).await;
+        let cache = cache();
+        let keys = ["avatars/2024/a.jpg?w=100", "original:avatars/2024/a.jpg", "avatars/2023/a.jpg?w=100"];
+        for key in keys {
+            cache.set(key, entry()).await;
+        }
+
+        let res = router(url, cache.clone()).oneshot(request("secret", r#"{"path": "/avatars/2024/"}"#)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, r#"{"surrogate_keys":["avatars/2024"]}"#);
+
+        assert_eq!(*received.lock().unwrap(), vec![(
+            Some("cdn-secret".to_string()),
+            r#"{"surrogate_keys":["avatars/2024"]}"#.to_string(),
+        )]);
+
+        assert_eq!(cache.get(keys[0]).await, None);
+        assert_eq!(cache.get(keys[1]).await, None);
+        assert_eq!(cache.get(keys[2]).await.map(|e| e.content), Some(entry().content));
+    }
+
+    #[tokio::test]
+    async fn test_purge_rejects_requests() {
+        let (url, received) = cdn(StatusCode::OK).await;
+        let testcases = vec![
+            ("wrong", r#"{"path": "a.jpg"}"#, StatusCode::UNAUTHORIZED),
+            ("", r#"{"path": "a.jpg"}"#, StatusCode::UNAUTHORIZED),
+            ("secret", r#"{"path": "/"}"#, StatusCode::BAD_REQUEST),
+            ("secret", "a.jpg", StatusCode::BAD_REQUEST),
+        ];
+
+        for (token, body, expected) in testcases {
+            let res = router(url.clone(), cache()).oneshot(request(token, body)).await.unwrap();
+            assert_eq!(res.status(), expected, "{} {}", token, body);
+        }
+        assert!(received.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_purge_webhook_error() {
+        let (url, _) = cdn(StatusCode::INTERNAL_SERVER_ERROR).await;
+
+        let res = router(url, cache()).oneshot(request("secret", r#"{"path": "a.jpg"}"#)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_GATEWAY);
+    }
+}
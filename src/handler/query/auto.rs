@@ -1,6 +1,5 @@
-use std::str::FromStr;
-use serde::de::IntoDeserializer;
 use serde::Deserialize;
+#[cfg(test)]
 use crate::handler::query::vec::CommaSeparatedVec;
 
 #[derive(Debug, Deserialize, PartialEq)]
@@ -12,6 +11,7 @@ pub(crate) enum AutoFeature {
 }
 
 impl AutoFeature {
+    #[cfg(test)]
     pub fn from_iter<I: IntoIterator<Item=AutoFeature>>(iter: I) -> CommaSeparatedVec<AutoFeature> {
         CommaSeparatedVec::from_iter(iter)
     }
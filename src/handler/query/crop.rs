@@ -35,7 +35,7 @@ impl<'de> Deserialize<'de> for Crop {
                 E: de::Error,
             {
                 let set: HashSet<&str> = value.split(',').collect();
-                match set.iter().map(|&s| s).collect::<Vec<_>>().as_slice() {
+                match set.iter().copied().collect::<Vec<_>>().as_slice() {
                     ["top"] => Ok(Crop::Top),
                     ["top", "left"] | ["left", "top"] => Ok(Crop::TopLeft),
                     ["top", "right"] | ["right", "top"] => Ok(Crop::TopRight),
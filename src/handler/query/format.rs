@@ -0,0 +1,65 @@
+use image::ImageFormat;
+use serde::Deserialize;
+
+/// Output format requested with `fm`.
+#[derive(Debug, Deserialize, PartialEq, Clone, Copy)]
+pub enum Format {
+    #[serde(rename = "jpg", alias = "jpeg")]
+    Jpeg,
+    #[serde(rename = "png")]
+    Png,
+    #[serde(rename = "webp")]
+    WebP,
+    #[serde(rename = "gif")]
+    Gif,
+    #[serde(rename = "avif")]
+    Avif,
+    #[serde(rename = "bmp")]
+    Bmp,
+    #[serde(rename = "tiff", alias = "tif")]
+    Tiff,
+}
+
+impl From<Format> for ImageFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Jpeg => ImageFormat::Jpeg,
+            Format::Png => ImageFormat::Png,
+            Format::WebP => ImageFormat::WebP,
+            Format::Gif => ImageFormat::Gif,
+            Format::Avif => ImageFormat::Avif,
+            Format::Bmp => ImageFormat::Bmp,
+            Format::Tiff => ImageFormat::Tiff,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::de::{self, IntoDeserializer};
+    use serde::de::value::StrDeserializer;
+    use super::*;
+
+    type E = de::value::Error;
+
+    #[test]
+    fn test_deserialize() {
+        let testcases = vec![
+            ("jpg", Some(Format::Jpeg)),
+            ("jpeg", Some(Format::Jpeg)),
+            ("png", Some(Format::Png)),
+            ("webp", Some(Format::WebP)),
+            ("gif", Some(Format::Gif)),
+            ("avif", Some(Format::Avif)),
+            ("bmp", Some(Format::Bmp)),
+            ("tif", Some(Format::Tiff)),
+            ("svg", None),
+            ("", None),
+        ];
+
+        for (value, expected) in testcases {
+            let format = Format::deserialize::<StrDeserializer<E>>(value.into_deserializer()).ok();
+            assert_eq!(format, expected, "{}", value);
+        }
+    }
+}
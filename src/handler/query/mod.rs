@@ -6,11 +6,18 @@ mod auto;
 mod vec;
 mod monochrome;
 mod flip;
+mod format;
+mod quality;
 
 pub use params::ProcessParams;
 pub use fit::Fit;
 pub(crate) use crop::Crop;
 pub(crate) use flip::Flip;
+pub use format::Format;
+#[allow(unused_imports)]
+pub(crate) use quality::Quality;
+#[allow(unused_imports)]
 pub(crate) use rotate::Rotate;
+#[allow(unused_imports)]
 pub(crate) use auto::AutoFeature;
 pub(crate) use monochrome::MonoChrome;
@@ -4,8 +4,8 @@ use serde::de::Visitor;
 
 #[derive(Debug, PartialEq)]
 pub(crate) enum MonoChrome {
-    RGB(u8, u8, u8),
-    ARGB(u8, u8, u8, u8),
+    Rgb(u8, u8, u8),
+    Argb(u8, u8, u8, u8),
 }
 
 impl<'de> Deserialize<'de> for MonoChrome {
@@ -34,7 +34,7 @@ impl<'de> Deserialize<'de> for MonoChrome {
 
                 match hex.len() {
                     8 => {
-                        Ok(MonoChrome::ARGB(
+                        Ok(MonoChrome::Argb(
                             parse_hex(&hex[0..2])?,
                             parse_hex(&hex[2..4])?,
                             parse_hex(&hex[4..6])?,
@@ -42,14 +42,14 @@ impl<'de> Deserialize<'de> for MonoChrome {
                         ))
                     }
                     6 => {
-                        Ok(MonoChrome::RGB(
+                        Ok(MonoChrome::Rgb(
                             parse_hex(&hex[0..2])?,
                             parse_hex(&hex[2..4])?,
                             parse_hex(&hex[4..6])?,
                         ))
                     }
                     4 => {
-                        Ok(MonoChrome::ARGB(
+                        Ok(MonoChrome::Argb(
                             parse_hex(&format!("{0}{0}", &hex[0..1]))?,
                             parse_hex(&format!("{0}{0}", &hex[1..2]))?,
                             parse_hex(&format!("{0}{0}", &hex[2..3]))?,
@@ -57,7 +57,7 @@ impl<'de> Deserialize<'de> for MonoChrome {
                         ))
                     }
                     3 => {
-                        Ok(MonoChrome::RGB(
+                        Ok(MonoChrome::Rgb(
                             parse_hex(&format!("{0}{0}", &hex[0..1]))?,
                             parse_hex(&format!("{0}{0}", &hex[1..2]))?,
                             parse_hex(&format!("{0}{0}", &hex[2..3]))?,
@@ -83,17 +83,17 @@ mod tests {
     #[test]
     fn test_monochrome() {
         let testcases: Vec<(&str, MonoChrome)> = vec![
-            ("#000000", MonoChrome::RGB(0, 0, 0)),
-            ("#ffffff", MonoChrome::RGB(255, 255, 255)),
-            ("FFFFFF", MonoChrome::RGB(255, 255, 255)),
-            ("#000", MonoChrome::RGB(0, 0, 0)),
-            ("#f00", MonoChrome::RGB(255, 0, 0)),
-            ("#0f0", MonoChrome::RGB(0, 255, 0)),
-            ("#00f", MonoChrome::RGB(0, 0, 255)),
-            ("#ff0000", MonoChrome::RGB(255, 0, 0)),
-            ("38141494", MonoChrome::ARGB(56, 20, 20, 148)),
-            ("A6000000", MonoChrome::ARGB(166, 0, 0, 0)),
-            ("A000", MonoChrome::ARGB(170, 0, 0, 0)),
+            ("#000000", MonoChrome::Rgb(0, 0, 0)),
+            ("#ffffff", MonoChrome::Rgb(255, 255, 255)),
+            ("FFFFFF", MonoChrome::Rgb(255, 255, 255)),
+            ("#000", MonoChrome::Rgb(0, 0, 0)),
+            ("#f00", MonoChrome::Rgb(255, 0, 0)),
+            ("#0f0", MonoChrome::Rgb(0, 255, 0)),
+            ("#00f", MonoChrome::Rgb(0, 0, 255)),
+            ("#ff0000", MonoChrome::Rgb(255, 0, 0)),
+            ("38141494", MonoChrome::Argb(56, 20, 20, 148)),
+            ("A6000000", MonoChrome::Argb(166, 0, 0, 0)),
+            ("A000", MonoChrome::Argb(170, 0, 0, 0)),
         ];
 
         for (input, expected) in testcases {
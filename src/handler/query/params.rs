@@ -1,12 +1,14 @@
+use std::num::NonZeroU16;
 use serde::Deserialize;
 use crate::handler::query::auto::AutoFeature;
 use crate::handler::query::crop::Crop;
 use crate::handler::query::fit::Fit;
 use crate::handler::query::flip::Flip;
+use crate::handler::query::format::Format;
+use crate::handler::query::quality::Quality;
 use crate::handler::query::monochrome::MonoChrome;
 use crate::handler::query::rotate::Rotate;
 use crate::handler::query::vec::CommaSeparatedVec;
-use crate::processor::Processor;
 
 macro_rules! impl_is_none {
     ($($field:ident),*) => {
@@ -40,12 +42,58 @@ pub struct ProcessParams {
     pub auto_features: Option<CommaSeparatedVec<AutoFeature>>,
 
     pub monochrome: Option<MonoChrome>,
+
+    #[serde(rename = "fm")]
+    pub format: Option<Format>,
+
+    #[serde(rename = "q")]
+    pub quality: Option<Quality>,
+
+    /// Renders only this frame of an animated source, counting from 1.
+    pub frame: Option<NonZeroU16>,
 }
 
 impl_is_none!(
-    width, height, blur, fit, crop, flip, rotate, auto_features, monochrome
+    width, height, blur, fit, crop, flip, rotate, auto_features, monochrome, format, quality, frame
 );
 
+impl ProcessParams {
+    /// Returns a canonical form of the params that is equal for requests rendering the
+    /// same output, regardless of how their query strings were written.
+    pub fn cache_key(&self) -> String {
+        let mut parts = vec![];
+
+        macro_rules! push {
+            ($name:literal, $field:expr) => {
+                if let Some(value) = &$field {
+                    parts.push(format!("{}={:?}", $name, value));
+                }
+            };
+        }
+
+        push!("w", self.width);
+        push!("h", self.height);
+        push!("blur", self.blur);
+        push!("fit", self.fit);
+        push!("crop", self.crop);
+        push!("flip", self.flip);
+        push!("rot", self.rotate.as_ref().map(|r| r.0));
+        push!("monochrome", self.monochrome);
+        push!("fm", self.format);
+        push!("q", self.quality.map(|q| q.0));
+        push!("frame", self.frame);
+
+        if let Some(features) = &self.auto_features {
+            let mut features: Vec<_> = features.iter().map(|f| format!("{:?}", f)).collect();
+            features.sort();
+            features.dedup();
+            parts.push(format!("auto={}", features.join(",")));
+        }
+
+        parts.join("&")
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use axum::extract::Query;
@@ -75,7 +123,49 @@ mod tests {
     fn test_query_params_monochrome() {
         let uri: Uri = "https://example.com/path/to/image?monochrome=000000".parse().unwrap();
         let params: Query<ProcessParams> = Query::try_from_uri(&uri).unwrap();
-        assert_eq!(params.monochrome, Some(MonoChrome::RGB(0, 0, 0)));
+        assert_eq!(params.monochrome, Some(MonoChrome::Rgb(0, 0, 0)));
+    }
+
+    #[test]
+    fn test_cache_key() {
+        let key = |query: &str| {
+            let uri: Uri = format!("https://example.com/image?{}", query).parse().unwrap();
+            Query::<ProcessParams>::try_from_uri(&uri).unwrap().cache_key()
+        };
+
+        assert_eq!(key(""), "");
+        assert_eq!(
+            key("fit=crop&crop=top,left&w=100&h=200&auto=format,compress&rot=-90"),
+            "w=100&h=200&fit=Crop&crop=TopLeft&rot=270.0&auto=Compress,Format"
+        );
+        assert_eq!(
+            key("w=100&h=200&fit=crop&crop=top,left&auto=compress,format&rot=270"),
+            key("fit=crop&crop=left,top&h=200&w=100&auto=format,compress,format&rot=-90"),
+        );
+        assert_ne!(key("w=100"), key("h=100"));
+        assert_eq!(key("fm=jpeg&w=100"), key("w=100&fm=jpg"));
+        assert_ne!(key("fm=png"), key("fm=webp"));
+        assert_ne!(key("q=50"), key("q=60"));
+        assert_ne!(key("frame=1"), key("frame=2"));
+    }
+
+    #[test]
+    fn test_query_params_format() {
+        let uri: Uri = "https://example.com/path/to/image?fm=webp".parse().unwrap();
+        let params: Query<ProcessParams> = Query::try_from_uri(&uri).unwrap();
+        assert_eq!(params.format, Some(Format::WebP));
+        assert!(!params.is_noop());
+    }
+
+    #[test]
+    fn test_query_params_frame() {
+        let uri: Uri = "https://example.com/path/to/image?frame=3".parse().unwrap();
+        let params: Query<ProcessParams> = Query::try_from_uri(&uri).unwrap();
+        assert_eq!(params.frame, NonZeroU16::new(3));
+        assert!(!params.is_noop());
+
+        let uri: Uri = "https://example.com/path/to/image?frame=0".parse().unwrap();
+        assert!(Query::<ProcessParams>::try_from_uri(&uri).is_err());
     }
 
     #[test]
@@ -0,0 +1,61 @@
+use std::fmt;
+use serde::{Deserialize, Deserializer};
+use serde::de::{self, Visitor};
+
+/// Encoder quality from 1 to 100, requested with `q`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Quality(pub u8);
+
+impl<'de> Deserialize<'de> for Quality {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct QualityVisitor;
+
+        impl<'de> Visitor<'de> for QualityVisitor {
+            type Value = Quality;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("an integer quality from 1 to 100")
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                match value.parse::<u8>() {
+                    Ok(quality @ 1..=100) => Ok(Quality(quality)),
+                    _ => Err(E::custom(format!("invalid quality: {}", value))),
+                }
+            }
+        }
+
+        deserializer.deserialize_str(QualityVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::de::{Error, IntoDeserializer};
+    use serde::de::value::StrDeserializer;
+    use super::*;
+
+    type E = de::value::Error;
+
+    #[test]
+    fn test_deserialize() {
+        let testcases = vec![
+            ("1", Ok(Quality(1))),
+            ("80", Ok(Quality(80))),
+            ("100", Ok(Quality(100))),
+            ("0", Err(Error::custom("invalid quality: 0"))),
+            ("101", Err(Error::custom("invalid quality: 101"))),
+            ("high", Err(Error::custom("invalid quality: high"))),
+        ];
+
+        for (value, expected) in testcases {
+            assert_eq!(Quality::deserialize::<StrDeserializer<E>>(value.into_deserializer()), expected);
+        }
+    }
+}
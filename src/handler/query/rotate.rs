@@ -1,5 +1,4 @@
 use std::fmt;
-use std::ops::{Deref, DerefMut};
 use serde::{Deserialize, Deserializer};
 use serde::de::{self, Visitor};
 
@@ -3,13 +3,13 @@ use std::fmt::Debug;
 use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
 use serde::{Deserialize, Deserializer};
-use serde::de::{self, IntoDeserializer, SeqAccess, Visitor};
-use serde::de::value::StrDeserializer;
+use serde::de::{self, IntoDeserializer, Visitor};
 
 #[derive(Debug, PartialEq)]
 pub(crate) struct CommaSeparatedVec<T>(Vec<T>);
 
 impl<T> CommaSeparatedVec<T> {
+    #[cfg(test)]
     pub fn from_iter<I: IntoIterator<Item=T>>(iter: I) -> Self {
         Self(iter.into_iter().collect())
     }
@@ -0,0 +1,179 @@
+use std::io::Cursor;
+use std::num::NonZeroU16;
+use std::sync::Arc;
+use axum::body::Bytes;
+use axum::http::StatusCode;
+use image::ImageFormat;
+use image::io::Reader as ImageReader;
+use sha2::{Digest, Sha256};
+use crate::config::Limits;
+use crate::config::color::Color;
+use crate::handler::Dependencies;
+use crate::handler::animation::{self, Animation};
+use crate::handler::encode::Settings;
+use crate::handler::error::Error;
+use crate::handler::limits;
+use crate::handler::query::ProcessParams;
+use crate::pool;
+use crate::processor::{Image, Processor};
+use crate::processor::procs::flatten::Flatten;
+use crate::processor::chainer::ChainProcessor;
+use crate::storage::GetResponse;
+
+/// An encoded image produced by running the processor chain over a source image.
+#[derive(Clone)]
+pub struct Rendered {
+    pub content: Bytes,
+    pub format: Option<ImageFormat>,
+    /// Description of the encoder settings, see `Settings::describe`.
+    pub compression: Option<String>,
+}
+
+/// Returns a strong ETag for the output of `params` over `source`, derived from the source
+/// ETag or, when the origin sends none, a hash of its content.
+pub fn etag(source: &GetResponse, params: &ProcessParams) -> String {
+    let mut hasher = Sha256::new();
+    match source.metadata.as_ref().and_then(|m| m.etag.as_deref()) {
+        Some(etag) => hasher.update(etag),
+        None => hasher.update(Sha256::digest(&source.content)),
+    }
+    hasher.update(b"?");
+    hasher.update(params.cache_key());
+
+    format!("\"{:x}\"", hasher.finalize())
+}
+
+/// Renders on the blocking pool, shedding the request with a 503 when its queue is full.
+pub async fn render(
+    deps: Arc<Dependencies>,
+    content: Vec<u8>,
+    params: ProcessParams,
+) -> Result<Rendered, Error> {
+    let (processor, limits) = (deps.processor.clone(), deps.limits);
+    let (background, avif_speed) = (deps.output.background, deps.output.avif_speed);
+
+    match deps.pool.run(move || render_blocking(&processor, &limits, background, avif_speed, content, params)).await {
+        Ok(rendered) => rendered,
+        Err(pool::Error::Full) => Err(StatusCode::SERVICE_UNAVAILABLE.into()),
+        Err(pool::Error::Panicked) => Err(StatusCode::INTERNAL_SERVER_ERROR.into()),
+    }
+}
+
+fn render_blocking(
+    processor: &ChainProcessor,
+    limits: &Limits,
+    background: Color,
+    avif_speed: u8,
+    content: Vec<u8>,
+    params: ProcessParams,
+) -> Result<Rendered, Error> {
+    let format = image::guess_format(&content).ok()
+        .or_else(|| is_avif(&content).then_some(ImageFormat::Avif));
+
+    // Formats that cannot hold an animation get its first frame, unless another one is selected.
+    let requested = params.format.map(ImageFormat::from);
+    let frame = params.frame
+        .or(requested.filter(|&f| !animation::supports_animation(f)).map(|_| NonZeroU16::MIN));
+
+    let mut animation = match animation::decode(&content, format, limits, frame)? {
+        Some(animation) => animation,
+        None => {
+            if let Some(frame) = params.frame.filter(|frame| frame.get() > 1) {
+                return Err(Error::FrameNotFound { frame: frame.get(), frames: 1 });
+            }
+
+            let mut reader = ImageReader::new(Cursor::new(content));
+            if let Some(format) = format {
+                reader.set_format(format);
+            }
+            reader.limits(limits::decoder(limits));
+
+            let decoded = reader
+                .decode()
+                .map_err(|err| limits::decode_error(limits, err))?;
+
+            Animation::still(match format {
+                Some(format) => Image::format(decoded, format),
+                None => Image::new(decoded),
+            })
+        }
+    };
+
+    let mut settings = Settings::new(ImageFormat::Jpeg, &params, avif_speed);
+    if processor.process(&mut animation.frames, params).is_err() {
+        return Err(StatusCode::INTERNAL_SERVER_ERROR.into());
+    }
+
+    // Animations stay in the source format unless another one is requested. Processors replace
+    // the image without carrying its format over.
+    let encoding = match animation.is_animated() {
+        true => requested.or(format),
+        false => requested.or(animation.frames[0].format).or(format),
+    }.unwrap_or(ImageFormat::Jpeg);
+
+    settings.format = encoding;
+    let content = match animation.is_animated() {
+        true => settings.encode_animation(&animation),
+        false => {
+            let image = &mut animation.frames[0];
+            if !supports_alpha(encoding) {
+                Flatten { background: background.0 }.process(image)
+                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            }
+            settings.encode(image)
+        }
+    }.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Rendered {
+        content: content.into(),
+        format: Some(encoding),
+        compression: settings.describe(),
+    })
+}
+
+/// Sniffs AVIF by the brand of its `ftyp` box, which `image` only recognises at a couple of box
+/// sizes.
+fn is_avif(content: &[u8]) -> bool {
+    matches!(content.get(4..12), Some(b"ftypavif" | b"ftypavis"))
+}
+
+fn supports_alpha(format: ImageFormat) -> bool {
+    !matches!(format, ImageFormat::Jpeg | ImageFormat::Bmp)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::extract::Query;
+    use crate::storage::Metadata;
+    use super::*;
+
+    fn params(query: &str) -> ProcessParams {
+        let uri = format!("/image.jpg?{}", query).parse().unwrap();
+        Query::<ProcessParams>::try_from_uri(&uri).unwrap().0
+    }
+
+    #[test]
+    fn test_etag() {
+        let source = |etag: Option<&str>, content: Vec<u8>| GetResponse {
+            content,
+            metadata: Some(Metadata {
+                content_type: None,
+                last_modified: None,
+                cache_control: None,
+                etag: etag.map(|e| e.to_string()),
+                content_length: None,
+            }),
+            ..GetResponse::default()
+        };
+
+        let a = etag(&source(Some("\"v1\""), vec![1]), &params("w=100&h=50"));
+        assert!(a.starts_with('"') && a.ends_with('"'));
+        assert_eq!(a, etag(&source(Some("\"v1\""), vec![2]), &params("h=50&w=100")));
+        assert_ne!(a, etag(&source(Some("\"v2\""), vec![1]), &params("w=100&h=50")));
+        assert_ne!(a, etag(&source(Some("\"v1\""), vec![1]), &params("w=100")));
+
+        let b = etag(&source(None, vec![1]), &params("w=100"));
+        assert_eq!(b, etag(&source(None, vec![1]), &params("w=100")));
+        assert_ne!(b, etag(&source(None, vec![2]), &params("w=100")));
+    }
+}
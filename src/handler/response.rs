@@ -1,29 +1,103 @@
 use std::collections::HashMap;
 use std::time::Duration;
-use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use axum::body::{Body, HttpBody};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
 use axum::response::IntoResponse;
 use image::ImageFormat;
 use axum::response::Response as AxumResponse;
+use crate::config::UpstreamMaxAge;
+use crate::storage::{self, Metadata};
 
+#[derive(Default)]
 pub struct Response {
-    pub image: (Vec<u8>, Option<ImageFormat>),
+    pub image: (Body, Option<ImageFormat>),
     pub cache_time: Duration,
+    pub backend: Option<String>,
+    /// Metadata of the source object as reported by the origin.
+    pub metadata: Option<Metadata>,
+    pub upstream_max_age: UpstreamMaxAge,
+    /// Set when serving part of the object, turning the response into a 206.
+    pub content_range: Option<String>,
+    pub etag: Option<String>,
+    /// Set when the client's cached copy is still valid, turning the response into a bodiless 304.
+    pub not_modified: bool,
+    pub stale_while_revalidate: Option<Duration>,
+    pub stale_if_error: Option<Duration>,
+    /// Set when the source failed and the image was rendered from a stale copy of it.
+    pub stale: bool,
+    /// Keys under which a CDN can purge the image, see `surrogate::keys`.
+    pub surrogate_keys: Vec<String>,
+    /// Encoder settings of a rendered image, sent as `X-Darkroom-Compression`.
+    pub compression: Option<String>,
+}
+
+impl Response {
+    /// Stale responses must not be cached, only kept around for when the source fails again.
+    fn cache_time(&self) -> Duration {
+        if self.stale {
+            return Duration::ZERO;
+        }
+        storage::cache_time(self.cache_time, self.metadata.as_ref(), self.upstream_max_age)
+    }
 }
 
 impl IntoResponse for Response {
     fn into_response(self) -> AxumResponse {
+        let mut cache_control = format!("public, max-age={}", self.cache_time().as_secs());
+        if let Some(stale_while_revalidate) = self.stale_while_revalidate.filter(|_| !self.stale) {
+            cache_control += &format!(", stale-while-revalidate={}", stale_while_revalidate.as_secs());
+        }
+        if let Some(stale_if_error) = self.stale_if_error {
+            cache_control += &format!(", stale-if-error={}", stale_if_error.as_secs());
+        }
+
         let mut headers = HashMap::from([
             ("Vary", "Accept".to_string()),
             ("Content-Disposition", "inline".to_string()),
-            ("Cache-Control", format!("public, max-age={}", self.cache_time.as_secs())),
+            ("Cache-Control", cache_control),
         ]);
 
-        let image = self.image.0;
-        let content_length = image.len();
-        headers.insert("Content-Length", content_length.to_string());
-        let mut res = AxumResponse::new(image.into());
+        if let Some(backend) = self.backend {
+            headers.insert("X-Darkroom-Backend", backend);
+        }
+        if let Some(last_modified) = self.metadata.as_ref().and_then(|m| m.last_modified) {
+            headers.insert("Last-Modified", httpdate::fmt_http_date(last_modified));
+        }
+        if let Some(etag) = self.etag {
+            headers.insert("ETag", etag);
+        }
+        if !self.surrogate_keys.is_empty() {
+            headers.insert("Surrogate-Key", self.surrogate_keys.join(" "));
+            headers.insert("Cache-Tag", self.surrogate_keys.join(","));
+        }
+        if let Some(compression) = self.compression {
+            headers.insert("X-Darkroom-Compression", compression);
+        }
+        if self.stale {
+            headers.insert("Warning", "110 - \"Response is Stale\"".to_string());
+        }
+
+        let mut res = if self.not_modified {
+            let mut res = AxumResponse::new(Body::empty());
+            *res.status_mut() = StatusCode::NOT_MODIFIED;
+            res
+        } else {
+            let image = self.image.0;
+            let content_length = image.size_hint().exact()
+                .or(self.metadata.and_then(|m| m.content_length));
+            if let Some(content_length) = content_length {
+                headers.insert("Content-Length", content_length.to_string());
+            }
+            let mut res = AxumResponse::new(image);
+
+            if let Some(content_range) = self.content_range {
+                *res.status_mut() = StatusCode::PARTIAL_CONTENT;
+                headers.insert("Content-Range", content_range);
+            }
+            res
+        };
 
-        if let Some(fmt) = self.image.1 {
+        if let Some(fmt) = self.image.1.filter(|_| !self.not_modified) {
             match fmt {
                 ImageFormat::Png => {
                     headers.insert("Content-Type", "image/png".to_string());
@@ -34,6 +108,18 @@ impl IntoResponse for Response {
                 ImageFormat::WebP => {
                     headers.insert("Content-Type", "image/webp".to_string());
                 }
+                ImageFormat::Gif => {
+                    headers.insert("Content-Type", "image/gif".to_string());
+                }
+                ImageFormat::Avif => {
+                    headers.insert("Content-Type", "image/avif".to_string());
+                }
+                ImageFormat::Bmp => {
+                    headers.insert("Content-Type", "image/bmp".to_string());
+                }
+                ImageFormat::Tiff => {
+                    headers.insert("Content-Type", "image/tiff".to_string());
+                }
                 _ => {}
             }
         }
@@ -54,20 +140,151 @@ impl IntoResponse for Response {
 
 #[cfg(test)]
 mod tests {
+    use std::time::SystemTime;
     use super::*;
 
+    /// A 3 byte JPEG cached for an hour, which tests override the fields they check of.
+    fn jpeg() -> Response {
+        Response {
+            image: (vec![1, 2, 3].into(), Some(ImageFormat::Jpeg)),
+            cache_time: Duration::from_secs(3600),
+            ..Response::default()
+        }
+    }
+
     #[test]
     fn test_into_response() {
+        let res = jpeg().into_response();
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.headers().get("Content-Type").unwrap(), "image/jpeg");
+        assert_eq!(res.headers().get("Content-Length").unwrap(), "3");
+        assert_eq!(res.headers().get("Cache-Control").unwrap(), "public, max-age=3600");
+        assert_eq!(res.headers().get("X-Darkroom-Backend"), None);
+        assert_eq!(res.headers().get("Warning"), None);
+        assert_eq!(res.headers().get("Surrogate-Key"), None);
+    }
+
+    #[test]
+    fn test_into_response_backend() {
+        let response = Response { backend: Some("legacy".to_string()), ..jpeg() };
+
+        let res = response.into_response();
+        assert_eq!(res.headers().get("X-Darkroom-Backend").unwrap(), "legacy");
+    }
+
+    #[test]
+    fn test_into_response_partial_content() {
         let response = Response {
-            image: (vec![1, 2, 3], Some(ImageFormat::Jpeg)),
-            cache_time: Duration::from_secs(3600),
+            image: (vec![1, 2].into(), None),
+            content_range: Some("bytes 0-1/3".to_string()),
+            ..jpeg()
+        };
+
+        let res = response.into_response();
+        assert_eq!(res.status(), 206);
+        assert_eq!(res.headers().get("Content-Range").unwrap(), "bytes 0-1/3");
+        assert_eq!(res.headers().get("Content-Length").unwrap(), "2");
+    }
+
+    #[test]
+    fn test_into_response_stream() {
+        let chunks: Vec<Result<_, std::io::Error>> = vec![Ok(vec![1, 2]), Ok(vec![3])];
+        let response = Response {
+            image: (Body::from_stream(futures::stream::iter(chunks)), None),
+            metadata: Some(Metadata { content_length: Some(3), ..Metadata::default() }),
+            ..jpeg()
         };
 
         let res = response.into_response();
-        assert_eq!(res.status(), 200);
-        assert_eq!(res.headers().get("Content-Type").unwrap(), "image/jpeg");
         assert_eq!(res.headers().get("Content-Length").unwrap(), "3");
+    }
+
+    #[test]
+    fn test_into_response_not_modified() {
+        let response = Response {
+            image: (Body::empty(), Some(ImageFormat::Jpeg)),
+            etag: Some("\"abc\"".to_string()),
+            not_modified: true,
+            ..jpeg()
+        };
+
+        let res = response.into_response();
+        assert_eq!(res.status(), 304);
+        assert_eq!(res.headers().get("ETag").unwrap(), "\"abc\"");
         assert_eq!(res.headers().get("Cache-Control").unwrap(), "public, max-age=3600");
+        assert_eq!(res.headers().get("Content-Type"), None);
+        assert_eq!(res.headers().get("Content-Length"), None);
+    }
+
+    #[test]
+    fn test_into_response_last_modified() {
+        let response = Response {
+            metadata: Some(Metadata {
+                last_modified: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1369353600)),
+                ..Metadata::default()
+            }),
+            ..jpeg()
+        };
+
+        let res = response.into_response();
+        assert_eq!(res.headers().get("Last-Modified").unwrap(), "Fri, 24 May 2013 00:00:00 GMT");
+    }
+
+    #[test]
+    fn test_into_response_upstream_max_age() {
+        let testcases = vec![
+            (UpstreamMaxAge::Cap, Some(60), "public, max-age=60"),
+            (UpstreamMaxAge::Cap, Some(7200), "public, max-age=3600"),
+            (UpstreamMaxAge::Cap, None, "public, max-age=3600"),
+            (UpstreamMaxAge::Override, Some(7200), "public, max-age=7200"),
+            (UpstreamMaxAge::Override, None, "public, max-age=3600"),
+            (UpstreamMaxAge::Ignore, Some(60), "public, max-age=3600"),
+        ];
+
+        for (policy, max_age, expected) in testcases {
+            let response = Response {
+                metadata: Some(Metadata { cache_control: max_age.map(Duration::from_secs), ..Metadata::default() }),
+                upstream_max_age: policy,
+                ..jpeg()
+            };
+
+            let res = response.into_response();
+            assert_eq!(res.headers().get("Cache-Control").unwrap(), expected, "{:?} {:?}", policy, max_age);
+            assert_eq!(res.headers().get("Last-Modified"), None);
+        }
+    }
+
+    #[test]
+    fn test_into_response_surrogate_keys() {
+        let response = Response {
+            surrogate_keys: vec!["avatars/a.jpg".to_string(), "avatars".to_string()],
+            ..jpeg()
+        };
+
+        let res = response.into_response();
+        assert_eq!(res.headers().get("Surrogate-Key").unwrap(), "avatars/a.jpg avatars");
+        assert_eq!(res.headers().get("Cache-Tag").unwrap(), "avatars/a.jpg,avatars");
+    }
+
+    #[test]
+    fn test_into_response_stale() {
+        let testcases = vec![
+            (false, "public, max-age=3600, stale-while-revalidate=60, stale-if-error=86400", None),
+            (true, "public, max-age=0, stale-if-error=86400", Some("110 - \"Response is Stale\"")),
+        ];
+
+        for (stale, cache_control, warning) in testcases {
+            let response = Response {
+                stale_while_revalidate: Some(Duration::from_secs(60)),
+                stale_if_error: Some(Duration::from_secs(86400)),
+                stale,
+                ..jpeg()
+            };
+
+            let res = response.into_response();
+            assert_eq!(res.headers().get("Cache-Control").unwrap(), cache_control, "stale: {}", stale);
+            assert_eq!(res.headers().get("Warning").and_then(|v| v.to_str().ok()), warning);
+        }
     }
 
     #[test]
@@ -76,15 +293,14 @@ mod tests {
             (ImageFormat::Png, Some("image/png")),
             (ImageFormat::Jpeg, Some("image/jpeg")),
             (ImageFormat::WebP, Some("image/webp")),
+            (ImageFormat::Gif, Some("image/gif")),
+            (ImageFormat::Avif, Some("image/avif")),
             // Catch all
             (ImageFormat::Farbfeld, None),
         ];
 
         for testcase in testcases {
-            let response = Response {
-                image: (vec![1, 2, 3], Some(testcase.0)),
-                cache_time: Duration::from_secs(3600),
-            };
+            let response = Response { image: (vec![1, 2, 3].into(), Some(testcase.0)), ..jpeg() };
 
             let res = response.into_response();
             assert_eq!(
@@ -5,13 +5,17 @@ mod prelude;
 mod handler;
 mod app;
 mod processor;
+mod coalesce;
+mod cache;
+mod cdn;
+mod pool;
+mod surrogate;
 
-use std::ops::Deref;
 use crate::config::Config;
 use crate::app::Server;
 use crate::prelude::Result;
 
-use opentelemetry::metrics::{MeterProvider, Unit};
+use opentelemetry::metrics::Unit;
 use opentelemetry_sdk::metrics::{Aggregation, Instrument, new_view, SdkMeterProvider, Stream};
 use prometheus::{Registry};
 use crate::error::Error::Generic;
@@ -0,0 +1,136 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use opentelemetry::metrics::{Counter, Histogram, Meter, Unit};
+use tokio::sync::Semaphore;
+use tokio::time::Instant;
+use crate::config::Pool;
+
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum Error {
+    #[error("blocking pool queue is full")]
+    Full,
+    #[error("blocking task panicked")]
+    Panicked,
+}
+
+/// Runs CPU-bound work on Tokio's blocking threads, `size` tasks at a time, so that it does
+/// not stall the async workers. Up to `queue_depth` tasks wait for a free thread; beyond
+/// that, tasks are rejected rather than queued.
+pub struct BlockingPool {
+    workers: Arc<Semaphore>,
+    admitted: AtomicUsize,
+    capacity: usize,
+    queue_wait: Histogram<f64>,
+    rejected: Counter<u64>,
+}
+
+/// Releases a task's place in the pool, also when the caller gives up waiting on it.
+struct Admission<'a>(&'a AtomicUsize);
+
+impl<'a> Drop for Admission<'a> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl BlockingPool {
+    pub fn new(cfg: &Pool, meter: &Meter) -> Self {
+        Self {
+            workers: Arc::new(Semaphore::new(cfg.size)),
+            admitted: AtomicUsize::new(0),
+            capacity: cfg.size + cfg.queue_depth,
+            queue_wait: meter.f64_histogram("blocking_pool_queue_wait_duration")
+                .with_description("Time tasks spent waiting for a blocking pool thread")
+                .with_unit(Unit::new("s"))
+                .init(),
+            rejected: meter.u64_counter("blocking_pool_rejected_tasks")
+                .with_description("Number of tasks rejected because the blocking pool queue was full")
+                .init(),
+        }
+    }
+
+    pub async fn run<F, T>(&self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        if self.admitted.fetch_add(1, Ordering::SeqCst) >= self.capacity {
+            self.admitted.fetch_sub(1, Ordering::SeqCst);
+            self.rejected.add(1, &[]);
+            return Err(Error::Full);
+        }
+        let _admission = Admission(&self.admitted);
+
+        let queued = Instant::now();
+        let permit = self.workers.clone().acquire_owned().await.expect("semaphore is never closed");
+        self.queue_wait.record(queued.elapsed().as_secs_f64(), &[]);
+
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            f()
+        }).await.map_err(|_| Error::Panicked)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use opentelemetry::metrics::MeterProvider;
+    use super::*;
+
+    fn pool(size: usize, queue_depth: usize) -> Arc<BlockingPool> {
+        Arc::new(BlockingPool::new(
+            &Pool { size, queue_depth },
+            &opentelemetry::global::meter_provider().meter("test-meter"),
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_run_limits_concurrency() {
+        let pool = pool(2, 8);
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let tasks = (0..6).map(|_| {
+            let (pool, running, peak) = (pool.clone(), running.clone(), peak.clone());
+            tokio::spawn(async move {
+                pool.run(move || {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    std::thread::sleep(Duration::from_millis(20));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                }).await
+            })
+        }).collect::<Vec<_>>();
+
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_run_rejects_when_full() {
+        let pool = pool(1, 1);
+
+        let busy = (0..2).map(|_| {
+            let pool = pool.clone();
+            tokio::spawn(async move { pool.run(|| std::thread::sleep(Duration::from_millis(100))).await })
+        }).collect::<Vec<_>>();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        assert_eq!(pool.run(|| ()).await, Err(Error::Full));
+        for task in busy {
+            task.await.unwrap().unwrap();
+        }
+        assert_eq!(pool.run(|| 1).await, Ok(1));
+    }
+
+    #[tokio::test]
+    async fn test_run_panicked() {
+        let pool = pool(1, 0);
+
+        assert_eq!(pool.run(|| panic!("boom")).await, Err::<(), _>(Error::Panicked));
+        assert_eq!(pool.run(|| 1).await, Ok(1));
+    }
+}
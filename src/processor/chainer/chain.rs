@@ -28,7 +28,8 @@ impl Processor {
         }
     }
 
-    pub fn process(&self, image: &mut Image, params: ProcessParams) -> Result<(), Error> {
+    /// Runs the processors for `params` over each frame, which is a single one for still images.
+    pub fn process(&self, frames: &mut [Image], params: ProcessParams) -> Result<(), Error> {
         let mut cb = ProcessorChainBuilder::new(self.histogram.clone());
 
         if let Some(flip) = params.flip {
@@ -73,7 +74,8 @@ impl Processor {
             cb.add_processor(BlurProcessor { radius: blur });
         }
 
-        cb.build().reduce(image)
+        let chain = cb.build();
+        frames.iter_mut().try_for_each(|image| chain.reduce(image))
     }
 }
 
@@ -81,7 +83,7 @@ impl Processor {
 mod tests {
     use image::{DynamicImage, RgbImage};
     use opentelemetry::metrics::MeterProvider;
-    use crate::handler::query::{AutoFeature, Crop, Flip, MonoChrome, Rotate};
+    use crate::handler::query::{AutoFeature, Crop, Flip, Format, MonoChrome, Quality, Rotate};
     use super::*;
 
     #[test]
@@ -96,7 +98,10 @@ mod tests {
                 blur: Some(3),
                 rotate: Some(Rotate(90.0)),
                 auto_features: Some(AutoFeature::from_iter(vec![AutoFeature::Compress])),
-                monochrome: Some(MonoChrome::ARGB(0, 0, 0, 0)),
+                monochrome: Some(MonoChrome::Argb(0, 0, 0, 0)),
+                format: Some(Format::Png),
+                quality: Some(Quality(80)),
+                frame: None,
             }, [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0].to_vec()),
             (ProcessParams {
                 width: Some(3),
@@ -123,7 +128,7 @@ mod tests {
                 ),
             };
             let mut image = Image::new(base_image);
-            processor.process(&mut image, testcase.0).unwrap();
+            processor.process(std::slice::from_mut(&mut image), testcase.0).unwrap();
             assert_eq!(&testcase.1, image.as_bytes());
         }
     }
@@ -1,5 +1,6 @@
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
+    #[allow(dead_code)]
     #[error("error: {0}")]
     Generic(String),
 }
@@ -1,7 +1,7 @@
 mod image;
 pub(crate) mod error;
 mod chain;
-mod procs;
+pub(crate) mod procs;
 
 pub(crate) mod chainer;
 
@@ -16,6 +16,7 @@ pub enum CropPoint {
     BottomLeft,
     Bottom,
     BottomRight,
+    #[allow(dead_code)]
     Custom(u32, u32),
 }
 
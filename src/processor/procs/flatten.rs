@@ -0,0 +1,28 @@
+use image::{DynamicImage, Rgb, RgbImage};
+use crate::processor::{Image, Processor};
+use crate::processor::error::Error;
+
+/// Composites a translucent image onto an opaque background, for formats without alpha.
+pub struct Flatten {
+    pub background: [u8; 3],
+}
+
+impl Processor for Flatten {
+    fn process(&self, image: &mut Image) -> Result<(), Error> {
+        if !image.color().has_alpha() {
+            return Ok(());
+        }
+
+        let rgba = image.to_rgba8();
+        let flattened = RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+            let [r, g, b, a] = rgba.get_pixel(x, y).0;
+            let blend = |c: u8, bg: u8| ((c as u16 * a as u16 + bg as u16 * (255 - a as u16) + 127) / 255) as u8;
+            Rgb([blend(r, self.background[0]), blend(g, self.background[1]), blend(b, self.background[2])])
+        });
+
+        let format = image.format;
+        *image = DynamicImage::ImageRgb8(flattened).into();
+        image.format = format;
+        Ok(())
+    }
+}
@@ -4,3 +4,4 @@ pub(crate) mod resize;
 pub(crate) mod rotate;
 pub(crate) mod monochrome;
 pub(crate) mod blur;
+pub(crate) mod flatten;
@@ -2,8 +2,8 @@ use crate::handler::query::{MonoChrome as QueryMonoChrome};
 use crate::processor::{Image, Processor};
 use crate::processor::error::Error;
 
-pub const BLACK: QueryMonoChrome = QueryMonoChrome::RGB(0, 0, 0);
-pub const BLACK_ALPHA: QueryMonoChrome = QueryMonoChrome::ARGB(0, 0, 0, 0);
+pub const BLACK: QueryMonoChrome = QueryMonoChrome::Rgb(0, 0, 0);
+pub const BLACK_ALPHA: QueryMonoChrome = QueryMonoChrome::Argb(0, 0, 0, 0);
 
 pub struct MonoChrome {
     pub color: QueryMonoChrome,
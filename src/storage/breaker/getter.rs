@@ -0,0 +1,294 @@
+use std::sync::{Arc, Mutex};
+use async_trait::async_trait;
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::{Gauge, Meter};
+use tokio::time::Instant;
+use crate::config::CircuitBreaker;
+use crate::storage::errors::Error;
+use crate::storage::getter::Getter;
+use crate::storage::{ByteStream, GetRequest, GetResponse};
+
+#[derive(Debug, PartialEq)]
+enum State {
+    Closed { since: Instant, requests: u32, failures: u32 },
+    Open { until: Instant },
+    HalfOpen { probes: u32, successes: u32 },
+}
+
+impl State {
+    fn closed() -> Self { State::Closed { since: Instant::now(), requests: 0, failures: 0 } }
+
+    /// Value reported by the state gauge.
+    fn gauge(&self) -> u64 {
+        match self {
+            State::Closed { .. } => 0,
+            State::HalfOpen { .. } => 1,
+            State::Open { .. } => 2,
+        }
+    }
+}
+
+/// Fails fast while the wrapped getter keeps failing, letting a limited number of probe
+/// requests through once `open_duration` has passed.
+pub struct CircuitBreakerGetter {
+    name: String,
+    inner: Arc<dyn Getter + Send + Sync>,
+    cfg: CircuitBreaker,
+    state: Mutex<State>,
+    gauge: Gauge<u64>,
+    attributes: [KeyValue; 1],
+}
+
+impl CircuitBreakerGetter {
+    pub fn new(name: String, inner: Arc<dyn Getter + Send + Sync>, cfg: CircuitBreaker, meter: Arc<Meter>) -> Self {
+        let breaker = Self {
+            inner,
+            cfg,
+            state: Mutex::new(State::closed()),
+            gauge: meter.u64_gauge("storage_circuit_breaker_state")
+                .with_description("Circuit breaker state: 0 closed, 1 half-open, 2 open")
+                .init(),
+            attributes: [KeyValue::new("source", name.clone())],
+            name,
+        };
+        breaker.gauge.record(0, &breaker.attributes);
+        breaker
+    }
+
+    fn transition(&self, state: &mut State, next: State) {
+        self.gauge.record(next.gauge(), &self.attributes);
+        *state = next;
+    }
+
+    /// Admits a request, returning whether it is a half-open probe.
+    fn acquire(&self) -> Result<bool, Error> {
+        let mut state = self.state.lock().unwrap();
+
+        if let State::Open { until } = *state {
+            if Instant::now() < until {
+                return Err(Error::CircuitOpen { name: self.name.clone() });
+            }
+            self.transition(&mut state, State::HalfOpen { probes: 0, successes: 0 });
+        }
+
+        match &mut *state {
+            State::HalfOpen { probes, successes } => {
+                if *probes + *successes >= self.cfg.half_open_probes {
+                    return Err(Error::CircuitOpen { name: self.name.clone() });
+                }
+                *probes += 1;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn record(&self, probe: bool, failed: bool) {
+        let mut state = self.state.lock().unwrap();
+
+        match &mut *state {
+            State::Closed { since, requests, failures } if !probe => {
+                if since.elapsed() >= self.cfg.window {
+                    (*since, *requests, *failures) = (Instant::now(), 0, 0);
+                }
+                *requests += 1;
+                *failures += failed as u32;
+
+                if *requests >= self.cfg.min_requests
+                    && *failures as f64 / *requests as f64 >= self.cfg.failure_ratio {
+                    let until = Instant::now() + self.cfg.open_duration;
+                    self.transition(&mut state, State::Open { until });
+                }
+            }
+            State::HalfOpen { probes, successes } if probe => {
+                *probes -= 1;
+                if failed {
+                    let until = Instant::now() + self.cfg.open_duration;
+                    self.transition(&mut state, State::Open { until });
+                } else {
+                    *successes += 1;
+                    if *successes >= self.cfg.half_open_probes {
+                        self.transition(&mut state, State::closed());
+                    }
+                }
+            }
+            // Results of requests admitted before the last transition.
+            _ => {}
+        }
+    }
+
+    fn release(&self) {
+        if let State::HalfOpen { probes, .. } = &mut *self.state.lock().unwrap() {
+            *probes -= 1;
+        }
+    }
+
+    fn is_failure(err: &Error) -> bool {
+        match err {
+            Error::ObjectNotFound { .. } | Error::OutsideRoot { .. } => false,
+            _ => err.status_code().is_server_error(),
+        }
+    }
+}
+
+/// Releases the probe slot of a half-open request that was dropped before completing.
+struct Permit<'a> {
+    breaker: &'a CircuitBreakerGetter,
+    probe: bool,
+}
+
+impl<'a> Permit<'a> {
+    fn complete(self, failed: bool) {
+        self.breaker.record(self.probe, failed);
+        std::mem::forget(self);
+    }
+}
+
+impl<'a> Drop for Permit<'a> {
+    fn drop(&mut self) {
+        if self.probe {
+            self.breaker.release();
+        }
+    }
+}
+
+#[async_trait]
+impl Getter for CircuitBreakerGetter {
+    async fn get(&self, req: GetRequest) -> Result<GetResponse, Error> {
+        let permit = Permit { breaker: self, probe: self.acquire()? };
+
+        let res = self.inner.get(req).await;
+        permit.complete(res.as_ref().is_err_and(Self::is_failure));
+
+        res
+    }
+
+    async fn get_stream(&self, req: GetRequest) -> Result<GetResponse<ByteStream>, Error> {
+        let permit = Permit { breaker: self, probe: self.acquire()? };
+
+        let res = self.inner.get_stream(req).await;
+        permit.complete(res.as_ref().is_err_and(Self::is_failure));
+
+        res
+    }
+
+    async fn purge(&self, key: &str) {
+        self.inner.purge(key).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::time::Duration;
+    use opentelemetry::metrics::MeterProvider;
+    use crate::storage::getter::MockGetter;
+    use super::*;
+
+    /// Returns a getter that fails with a 502 while `failing` is set.
+    fn origin() -> (MockGetter, Arc<AtomicBool>, Arc<AtomicUsize>) {
+        let failing = Arc::new(AtomicBool::new(true));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let (f, c) = (failing.clone(), calls.clone());
+
+        let mut mock = MockGetter::new();
+        mock.expect_get().returning(move |_| {
+            c.fetch_add(1, Ordering::SeqCst);
+            if f.load(Ordering::SeqCst) {
+                Err(Error::Upstream { status_code: Some(502), message: "".to_string() })
+            } else {
+                Ok(GetResponse::default())
+            }
+        });
+        (mock, failing, calls)
+    }
+
+    fn breaker(inner: MockGetter, open_duration: Duration) -> CircuitBreakerGetter {
+        CircuitBreakerGetter::new(
+            "origin".to_string(),
+            Arc::new(inner),
+            CircuitBreaker {
+                failure_ratio: 0.5,
+                min_requests: 4,
+                window: Duration::from_secs(60),
+                open_duration,
+                half_open_probes: 1,
+            },
+            Arc::new(opentelemetry::global::meter_provider().meter("test-meter")),
+        )
+    }
+
+    fn req() -> GetRequest {
+        GetRequest { path: "a.jpg".to_string(), options: None }
+    }
+
+    #[tokio::test]
+    async fn test_opens_on_failure_ratio() {
+        let (mock, _, calls) = origin();
+        let breaker = breaker(mock, Duration::from_secs(60));
+
+        for _ in 0..4 {
+            let err = breaker.get(req()).await.err().unwrap();
+            assert_eq!(err.status_code().as_u16(), 502);
+        }
+
+        let err = breaker.get(req()).await.err().unwrap();
+        assert!(matches!(err, Error::CircuitOpen { ref name } if name == "origin"));
+        assert_eq!(err.status_code().as_u16(), 503);
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_ignores_client_errors() {
+        let mut mock = MockGetter::new();
+        mock.expect_get()
+            .times(6)
+            .returning(|_| Err(Error::ObjectNotFound { path: "a.jpg".to_string() }));
+        let breaker = breaker(mock, Duration::from_secs(60));
+
+        for _ in 0..6 {
+            let err = breaker.get(req()).await.err().unwrap();
+            assert_eq!(err.status_code().as_u16(), 404);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_half_open_probe() {
+        let (mock, failing, calls) = origin();
+        let breaker = breaker(mock, Duration::from_millis(20));
+
+        for _ in 0..4 {
+            breaker.get(req()).await.err().unwrap();
+        }
+        assert!(matches!(*breaker.state.lock().unwrap(), State::Open { .. }));
+
+        // A failed probe opens the circuit again.
+        tokio::time::sleep(Duration::from_millis(25)).await;
+        breaker.get(req()).await.err().unwrap();
+        assert!(matches!(*breaker.state.lock().unwrap(), State::Open { .. }));
+        assert_eq!(calls.load(Ordering::SeqCst), 5);
+
+        // A successful probe closes it.
+        failing.store(false, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(25)).await;
+        breaker.get(req()).await.unwrap();
+        assert!(matches!(*breaker.state.lock().unwrap(), State::Closed { requests: 0, failures: 0, .. }));
+        breaker.get(req()).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 7);
+    }
+
+    #[tokio::test]
+    async fn test_half_open_limits_probes() {
+        let (mock, _, _) = origin();
+        let breaker = breaker(mock, Duration::ZERO);
+        *breaker.state.lock().unwrap() = State::HalfOpen { probes: 0, successes: 0 };
+
+        let permit = Permit { breaker: &breaker, probe: breaker.acquire().unwrap() };
+        assert!(permit.probe);
+        assert!(matches!(breaker.acquire(), Err(Error::CircuitOpen { .. })));
+
+        // Dropping an unfinished probe frees its slot.
+        drop(permit);
+        assert!(breaker.acquire().unwrap());
+    }
+}
@@ -0,0 +1,3 @@
+pub(crate) mod getter;
+
+pub use getter::CircuitBreakerGetter;
@@ -0,0 +1,190 @@
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use axum::body::Bytes;
+use crate::cache::{Cache, Entry};
+use crate::config::UpstreamMaxAge;
+use crate::surrogate;
+use crate::storage::errors::Error;
+use crate::storage::getter::Getter;
+use crate::storage::{self, ByteStream, GetRequest, GetResponse, Metadata};
+
+/// Keeps fetched originals in a cache, sparing the origin from refetching them. Ranged and
+/// conditional requests always go to the wrapped getter.
+pub struct CachingGetter {
+    inner: Arc<dyn Getter + Send + Sync>,
+    cache: Arc<dyn Cache + Send + Sync>,
+    cache_time: Duration,
+    upstream_max_age: UpstreamMaxAge,
+}
+
+impl CachingGetter {
+    /// Originals are kept for as long as their responses may be cached, see `storage::cache_time`.
+    pub fn new(
+        inner: Arc<dyn Getter + Send + Sync>,
+        cache: Arc<dyn Cache + Send + Sync>,
+        cache_time: Duration,
+        upstream_max_age: UpstreamMaxAge,
+    ) -> Self {
+        Self { inner, cache, cache_time, upstream_max_age }
+    }
+
+    const PREFIX: &'static str = "original:";
+
+    fn key(path: &str) -> String {
+        format!("{}{}", Self::PREFIX, path)
+    }
+
+    async fn cached(&self, req: &GetRequest) -> Option<GetResponse> {
+        if req.options.is_some() {
+            return None;
+        }
+        let entry = self.cache.get(&Self::key(&req.path)).await?;
+
+        Some(GetResponse {
+            metadata: Some(Metadata {
+                content_type: entry.content_type,
+                last_modified: entry.last_modified,
+                etag: entry.etag,
+                cache_control: entry.cache_control,
+                content_length: Some(entry.content.len() as u64),
+            }),
+            content: entry.content.to_vec(),
+            cache_time: Some(entry.cache_time),
+            backend: entry.backend,
+            ..GetResponse::default()
+        })
+    }
+}
+
+#[async_trait]
+impl Getter for CachingGetter {
+    async fn get(&self, req: GetRequest) -> Result<GetResponse, Error> {
+        if let Some(res) = self.cached(&req).await {
+            return Ok(res);
+        }
+
+        let (key, cacheable) = (Self::key(&req.path), req.options.is_none());
+        let res = self.inner.get(req).await?;
+
+        if cacheable && !res.stale {
+            let metadata = res.metadata.clone().unwrap_or_default();
+            let cache_time = res.cache_time.unwrap_or(self.cache_time);
+            self.cache.set(&key, Entry {
+                content: Bytes::from(res.content.clone()),
+                cache_time: storage::cache_time(cache_time, Some(&metadata), self.upstream_max_age),
+                content_type: metadata.content_type,
+                etag: metadata.etag,
+                last_modified: metadata.last_modified,
+                backend: res.backend.clone(),
+                cache_control: metadata.cache_control,
+            }).await;
+        }
+        Ok(res)
+    }
+
+    async fn get_stream(&self, req: GetRequest) -> Result<GetResponse<ByteStream>, Error> {
+        match self.cached(&req).await {
+            Some(res) => Ok(res.into_stream()),
+            None => self.inner.get_stream(req).await,
+        }
+    }
+
+    async fn purge(&self, key: &str) {
+        self.cache.purge(&|cached| {
+            cached.strip_prefix(Self::PREFIX).is_some_and(|path| surrogate::covers(key, path))
+        }).await;
+        self.inner.purge(key).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockall::predicate::*;
+    use opentelemetry::metrics::MeterProvider;
+    use crate::cache::memory::MemoryCache;
+    use crate::storage::getter::MockGetter;
+    use crate::storage::{ByteRange, GetRequestOptions};
+    use super::*;
+
+    fn getter(inner: MockGetter) -> CachingGetter {
+        CachingGetter::new(
+            Arc::new(inner),
+            Arc::new(MemoryCache::new(1024, &opentelemetry::global::meter_provider().meter("test-meter"))),
+            Duration::from_secs(60),
+            UpstreamMaxAge::Cap,
+        )
+    }
+
+    fn req(options: Option<GetRequestOptions>) -> GetRequest {
+        GetRequest { path: "a.jpg".to_string(), options }
+    }
+
+    #[tokio::test]
+    async fn test_get() {
+        let mut mock = MockGetter::new();
+        mock.expect_get()
+            .with(eq(req(None)))
+            .times(1)
+            .returning(|_| Ok(GetResponse {
+                content: vec![1, 2, 3],
+                metadata: Some(Metadata {
+                    etag: Some("\"v1\"".to_string()),
+                    cache_control: Some(Duration::from_secs(10)),
+                    ..Metadata::default()
+                }),
+                ..GetResponse::default()
+            }));
+        let getter = getter(mock);
+
+        for _ in 0..2 {
+            let res = getter.get(req(None)).await.unwrap();
+            assert_eq!(res.content, vec![1, 2, 3]);
+            let metadata = res.metadata.unwrap();
+            assert_eq!(metadata.etag, Some("\"v1\"".to_string()));
+            assert_eq!(metadata.cache_control, Some(Duration::from_secs(10)));
+        }
+        let cache_time = getter.cache.get("original:a.jpg").await.unwrap().cache_time;
+        assert!(cache_time <= Duration::from_secs(10) && cache_time > Duration::from_secs(9));
+    }
+
+    #[tokio::test]
+    async fn test_purge() {
+        let mut mock = MockGetter::new();
+        mock.expect_get()
+            .times(2)
+            .returning(|_| Ok(GetResponse { content: vec![1], ..GetResponse::default() }));
+        mock.expect_purge().with(eq("a.jpg")).times(1).return_const(());
+        let getter = getter(mock);
+
+        getter.get(req(None)).await.unwrap();
+        getter.purge("a.jpg").await;
+        getter.get(req(None)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_get_skips_stale_responses() {
+        let mut mock = MockGetter::new();
+        mock.expect_get()
+            .times(2)
+            .returning(|_| Ok(GetResponse { content: vec![1], stale: true, ..GetResponse::default() }));
+        let getter = getter(mock);
+
+        for _ in 0..2 {
+            assert!(getter.get(req(None)).await.unwrap().stale);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_bypasses_ranged_requests() {
+        let options = Some(GetRequestOptions { range: Some(ByteRange::new("bytes=0-0")), ..GetRequestOptions::default() });
+        let mut mock = MockGetter::new();
+        mock.expect_get()
+            .times(2)
+            .returning(|_| Ok(GetResponse { content: vec![1], ..GetResponse::default() }));
+        let getter = getter(mock);
+
+        getter.get(req(options.clone())).await.unwrap();
+        getter.get(req(options)).await.unwrap();
+    }
+}
@@ -0,0 +1,185 @@
+use axum::http::header::CONTENT_RANGE;
+use axum::http::StatusCode;
+use futures::{StreamExt, TryStreamExt};
+use reqwest::{Client, Proxy, Response};
+use crate::config::NetworkConfig;
+use crate::storage::errors::Error;
+use crate::storage::{ByteStream, GetResponse, Metadata};
+
+/// Most that is allocated up front for a body, whatever length the origin announces.
+const MAX_PREALLOCATION: u64 = 1 << 20;
+
+/// Builds the HTTP client used by a source to talk to its origin.
+pub fn new(cfg: &NetworkConfig) -> reqwest::Result<Client> {
+    let mut builder = Client::builder().timeout(cfg.timeout);
+
+    if let Some(timeout) = cfg.connect_timeout {
+        builder = builder.connect_timeout(timeout);
+    }
+    if let Some(timeout) = cfg.pool_idle_timeout {
+        builder = builder.pool_idle_timeout(timeout);
+    }
+    if let Some(max) = cfg.pool_max_idle_per_host {
+        builder = builder.pool_max_idle_per_host(max);
+    }
+    if cfg.http2_prior_knowledge.unwrap_or(false) {
+        builder = builder.http2_prior_knowledge();
+    }
+    if let Some(proxy) = &cfg.proxy {
+        builder = builder.proxy(Proxy::all(proxy.as_str())?);
+    }
+    if let Some(user_agent) = &cfg.user_agent {
+        builder = builder.user_agent(user_agent);
+    }
+
+    builder.build()
+}
+
+/// Reads the whole body of a successful response from the origin, refusing bodies over
+/// `max_bytes` without reading more than that.
+pub async fn read(res: Response, max_bytes: Option<u64>) -> Result<GetResponse, Error> {
+    let limit = max_bytes.unwrap_or(u64::MAX);
+    if res.content_length().is_some_and(|len| len > limit) {
+        return Err(Error::TooLarge { limit });
+    }
+
+    let head = head(&res);
+    // Content-Length is only a hint, the body grows past this as it is read.
+    let capacity = res.content_length().unwrap_or(0).min(limit).min(MAX_PREALLOCATION);
+    let mut body = Vec::with_capacity(capacity as usize);
+    let mut chunks = res.bytes_stream();
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk.map_err(Error::Reqwest)?;
+        if (body.len() + chunk.len()) as u64 > limit {
+            return Err(Error::TooLarge { limit });
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(head.map(|_| body))
+}
+
+/// Streams the body of a successful response from the origin.
+pub fn stream(res: Response) -> GetResponse<ByteStream> {
+    head(&res).map(|_| res.bytes_stream().map_err(Error::Reqwest).boxed())
+}
+
+fn head(res: &Response) -> GetResponse<()> {
+    let content_range = match res.status() {
+        StatusCode::PARTIAL_CONTENT => res.headers()
+            .get(CONTENT_RANGE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string()),
+        _ => None,
+    };
+
+    GetResponse {
+        content: (),
+        metadata: Some(Metadata::from(res.headers())),
+        cache_time: None,
+        backend: None,
+        content_range,
+        stale: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::time::Duration;
+    use axum::body::Body;
+    use axum::http::header::USER_AGENT;
+    use axum::http::HeaderMap;
+    use axum::routing::get;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use crate::config::url::Url;
+    use super::*;
+
+    async fn stand_in() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let router = axum::Router::new()
+            .route("/agent", get(|headers: HeaderMap| async move {
+                headers.get(USER_AGENT).and_then(|v| v.to_str().ok()).unwrap_or_default().to_string()
+            }))
+            .route("/large", get(|| async { vec![0u8; 64] }))
+            .route("/chunked", get(|| async {
+                let chunks: Vec<Result<_, std::io::Error>> = vec![Ok(vec![0u8; 32]), Ok(vec![0u8; 32])];
+                Body::from_stream(futures::stream::iter(chunks))
+            }))
+            .route("/slow", get(|| async {
+                tokio::time::sleep(Duration::from_secs(2)).await;
+            }));
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_user_agent() {
+        let addr = stand_in().await;
+        let client = new(&NetworkConfig {
+            user_agent: Some("darkroom-rs/test".to_string()),
+            ..NetworkConfig::default()
+        }).unwrap();
+
+        let res = client.get(format!("http://{}/agent", addr)).send().await.unwrap();
+        assert_eq!(res.text().await.unwrap(), "darkroom-rs/test");
+    }
+
+    #[tokio::test]
+    async fn test_read_max_bytes() {
+        let addr = stand_in().await;
+        let client = new(&NetworkConfig::default()).unwrap();
+        let read = |path: &'static str, max_bytes| {
+            let req = client.get(format!("http://{}{}", addr, path));
+            async move { read(req.send().await.unwrap(), max_bytes).await }
+        };
+
+        assert_eq!(read("/large", Some(64)).await.unwrap().content.len(), 64);
+        assert_eq!(read("/chunked", None).await.unwrap().content.len(), 64);
+        // Refused up front by the Content-Length, or once the body grows over the limit.
+        assert!(matches!(read("/large", Some(63)).await, Err(Error::TooLarge { limit: 63 })));
+        assert!(matches!(read("/chunked", Some(40)).await, Err(Error::TooLarge { limit: 40 })));
+    }
+
+    #[tokio::test]
+    async fn test_read_untrusted_content_length() {
+        // Announces a terabyte and sends three bytes, which must fail the read, not the allocation.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut req = [0; 1024];
+            let _ = socket.read(&mut req).await.unwrap();
+            socket.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 1099511627776\r\n\r\nabc").await.unwrap();
+        });
+
+        let res = new(&NetworkConfig::default()).unwrap().get(format!("http://{}/", addr)).send().await.unwrap();
+        assert!(matches!(read(res, None).await, Err(Error::Reqwest(_))));
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let addr = stand_in().await;
+        let client = new(&NetworkConfig {
+            timeout: Duration::from_millis(100),
+            ..NetworkConfig::default()
+        }).unwrap();
+
+        let err = client.get(format!("http://{}/slow", addr)).send().await.unwrap_err();
+        assert!(err.is_timeout());
+    }
+
+    #[tokio::test]
+    async fn test_proxy() {
+        let addr = stand_in().await;
+        let client = new(&NetworkConfig {
+            proxy: Some(Url::new(&format!("http://{}", addr)).unwrap()),
+            ..NetworkConfig::default()
+        }).unwrap();
+
+        // The stand-in receives the request for the unresolvable origin as the proxy.
+        let res = client.get("http://origin.invalid/agent").send().await.unwrap();
+        assert_eq!(res.status(), 200);
+    }
+}
@@ -0,0 +1,77 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+use opentelemetry::metrics::Meter;
+use crate::coalesce::Coalescer;
+use crate::storage::errors::Error;
+use crate::storage::getter::Getter;
+use crate::storage::{ByteStream, GetRequest, GetResponse};
+
+/// Shares a single origin fetch between concurrent identical requests.
+pub struct CoalescingGetter {
+    inner: Arc<dyn Getter + Send + Sync>,
+    coalescer: Coalescer<GetRequest, Result<GetResponse, Error>>,
+}
+
+impl CoalescingGetter {
+    pub fn new(inner: Arc<dyn Getter + Send + Sync>, meter: &Meter) -> Self {
+        Self { inner, coalescer: Coalescer::new("storage", meter) }
+    }
+}
+
+#[async_trait]
+impl Getter for CoalescingGetter {
+    async fn get(&self, req: GetRequest) -> Result<GetResponse, Error> {
+        let inner = self.inner.clone();
+        self.coalescer.run(req.clone(), async move { inner.get(req).await }).await
+    }
+
+    /// Streams cannot be shared, so streamed requests always reach the origin.
+    async fn get_stream(&self, req: GetRequest) -> Result<GetResponse<ByteStream>, Error> {
+        self.inner.get_stream(req).await
+    }
+
+    async fn purge(&self, key: &str) {
+        self.inner.purge(key).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use opentelemetry::metrics::MeterProvider;
+    use super::*;
+
+    struct Slow {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Getter for Slow {
+        async fn get(&self, req: GetRequest) -> Result<GetResponse, Error> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            match req.path.as_str() {
+                "a.jpg" => Ok(GetResponse { content: vec![1], ..GetResponse::default() }),
+                _ => Err(Error::ObjectNotFound { path: req.path }),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get() {
+        let origin = Arc::new(Slow { calls: AtomicUsize::new(0) });
+        let getter = CoalescingGetter::new(
+            origin.clone(),
+            &opentelemetry::global::meter_provider().meter("test-meter"),
+        );
+        let req = |path: &str| GetRequest { path: path.to_string(), options: None };
+
+        let (a1, a2, b) = tokio::join!(getter.get(req("a.jpg")), getter.get(req("a.jpg")), getter.get(req("b.jpg")));
+
+        assert_eq!(a1.unwrap().content, vec![1]);
+        assert_eq!(a2.unwrap().content, vec![1]);
+        assert!(matches!(b, Err(Error::ObjectNotFound { .. })));
+        assert_eq!(origin.calls.load(Ordering::SeqCst), 2);
+    }
+}
@@ -1,11 +1,27 @@
 use axum::http::StatusCode;
+use crate::storage::Metadata;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("object not found at: {path}")]
     ObjectNotFound { path: String },
+    #[error("path escapes the storage root: {path}")]
+    OutsideRoot { path: String },
+    #[error("io error: {0}")]
+    IO(#[from] std::io::Error),
     #[error("reqwest error: {0}")]
     Reqwest(#[from] reqwest::Error),
+    #[error("invalid url: {0}")]
+    InvalidUrl(#[from] url::ParseError),
+    #[error("circuit breaker is open for: {name}")]
+    CircuitOpen { name: String },
+    /// Validators and caching headers of the origin's 304 are kept for the client's 304.
+    #[error("object not modified at: {path}")]
+    NotModified { path: String, metadata: Metadata },
+    #[error("range not satisfiable: {range}")]
+    RangeNotSatisfiable { range: String },
+    #[error("object is larger than the limit of {limit} bytes")]
+    TooLarge { limit: u64 },
     #[error("upstream error: {message}")]
     Upstream { status_code: Option<u16>, message: String },
 }
@@ -15,8 +31,41 @@ impl Error {
         match self {
             Error::Upstream { status_code, .. } => StatusCode::from_u16(status_code.unwrap_or(500)).unwrap(),
             Error::ObjectNotFound { .. } => StatusCode::NOT_FOUND,
+            Error::OutsideRoot { .. } => StatusCode::FORBIDDEN,
+            Error::CircuitOpen { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            Error::NotModified { .. } => StatusCode::NOT_MODIFIED,
+            Error::RangeNotSatisfiable { .. } => StatusCode::RANGE_NOT_SATISFIABLE,
+            Error::TooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            Error::IO(_) | Error::InvalidUrl(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Error::Reqwest(err) => err.status().unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
-            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Clones the error for sharing it between coalesced requests. Errors wrapping a
+/// non-cloneable source keep their message and status code.
+impl Clone for Error {
+    fn clone(&self) -> Self {
+        match self {
+            Error::ObjectNotFound { path } => Error::ObjectNotFound { path: path.clone() },
+            Error::OutsideRoot { path } => Error::OutsideRoot { path: path.clone() },
+            Error::IO(err) => Error::IO(std::io::Error::new(err.kind(), err.to_string())),
+            Error::InvalidUrl(err) => Error::InvalidUrl(*err),
+            Error::Reqwest(err) => Error::Upstream {
+                status_code: Some(self.status_code().as_u16()),
+                message: err.to_string(),
+            },
+            Error::CircuitOpen { name } => Error::CircuitOpen { name: name.clone() },
+            Error::NotModified { path, metadata } => Error::NotModified {
+                path: path.clone(),
+                metadata: metadata.clone(),
+            },
+            Error::RangeNotSatisfiable { range } => Error::RangeNotSatisfiable { range: range.clone() },
+            Error::TooLarge { limit } => Error::TooLarge { limit: *limit },
+            Error::Upstream { status_code, message } => Error::Upstream {
+                status_code: *status_code,
+                message: message.clone(),
+            },
         }
     }
 }
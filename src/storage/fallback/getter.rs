@@ -0,0 +1,186 @@
+use std::future::Future;
+use std::sync::Arc;
+use async_trait::async_trait;
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::{Counter, Meter};
+use crate::storage::errors::Error;
+use crate::storage::getter::Getter;
+use crate::storage::{ByteStream, GetRequest, GetResponse};
+
+/// Tries each backend in order, moving on to the next one when the object is missing or
+/// the backend fails with a server error.
+pub struct FallbackGetter {
+    backends: Vec<(String, Arc<dyn Getter + Send + Sync>)>,
+    counter: Counter<u64>,
+}
+
+impl FallbackGetter {
+    pub fn new(backends: Vec<(String, Arc<dyn Getter + Send + Sync>)>, meter: Arc<Meter>) -> Self {
+        Self {
+            backends,
+            counter: meter.u64_counter("storage_fallback_served")
+                .with_description("Number of objects served by each backend of a fallback chain")
+                .init(),
+        }
+    }
+
+    /// Returns the response of the first backend that serves the object.
+    async fn first<C, F, Fut>(&self, req: GetRequest, get: F) -> Result<GetResponse<C>, Error>
+    where
+        F: Fn(Arc<dyn Getter + Send + Sync>, GetRequest) -> Fut,
+        Fut: Future<Output=Result<GetResponse<C>, Error>>,
+    {
+        let mut last_err = Error::ObjectNotFound { path: req.path.clone() };
+
+        for (name, backend) in &self.backends {
+            match get(backend.clone(), req.clone()).await {
+                Ok(mut res) => {
+                    let served_by = res.backend.get_or_insert_with(|| name.clone());
+                    self.counter.add(1, &[KeyValue::new("backend", served_by.clone())]);
+                    return Ok(res);
+                }
+                Err(err) if Self::should_fall_through(&err) => last_err = err,
+                Err(err) => return Err(err),
+            }
+        }
+
+        Err(last_err)
+    }
+
+    fn should_fall_through(err: &Error) -> bool {
+        match err {
+            Error::ObjectNotFound { .. } => true,
+            Error::Upstream { .. } => err.status_code().is_server_error(),
+            _ => false,
+        }
+    }
+}
+
+#[async_trait]
+impl Getter for FallbackGetter {
+    async fn get(&self, req: GetRequest) -> Result<GetResponse, Error> {
+        self.first(req, |backend, req| async move { backend.get(req).await }).await
+    }
+
+    async fn get_stream(&self, req: GetRequest) -> Result<GetResponse<ByteStream>, Error> {
+        self.first(req, |backend, req| async move { backend.get_stream(req).await }).await
+    }
+
+    async fn purge(&self, key: &str) {
+        for (_, backend) in &self.backends {
+            backend.purge(key).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockall::predicate::*;
+    use opentelemetry::metrics::MeterProvider;
+    use tokio::net::TcpListener;
+    use crate::config::NetworkConfig;
+    use crate::config::url::Url;
+    use crate::storage::getter::MockGetter;
+    use crate::storage::webfolder::WebFolderGetter;
+    use super::*;
+
+    fn backend(result: fn() -> Result<GetResponse, Error>, times: usize) -> Arc<dyn Getter + Send + Sync> {
+        let mut mock = MockGetter::new();
+        mock.expect_get()
+            .with(eq(GetRequest { path: "a.jpg".to_string(), options: None }))
+            .times(times)
+            .returning(move |_| result());
+        Arc::new(mock)
+    }
+
+    fn fallback(backends: Vec<(&str, Arc<dyn Getter + Send + Sync>)>) -> FallbackGetter {
+        FallbackGetter::new(
+            backends.into_iter().map(|(n, b)| (n.to_string(), b)).collect(),
+            Arc::new(opentelemetry::global::meter_provider().meter("test-meter")),
+        )
+    }
+
+    fn req() -> GetRequest {
+        GetRequest { path: "a.jpg".to_string(), options: None }
+    }
+
+    fn found() -> Result<GetResponse, Error> {
+        Ok(GetResponse { content: vec![1], ..GetResponse::default() })
+    }
+
+    fn not_found() -> Result<GetResponse, Error> {
+        Err(Error::ObjectNotFound { path: "a.jpg".to_string() })
+    }
+
+    fn upstream(status_code: u16) -> Result<GetResponse, Error> {
+        Err(Error::Upstream { status_code: Some(status_code), message: "".to_string() })
+    }
+
+    #[tokio::test]
+    async fn test_get_falls_through() {
+        let getter = fallback(vec![
+            ("bucket", backend(not_found, 1)),
+            ("origin", backend(|| upstream(503), 1)),
+            ("legacy", backend(found, 1)),
+            ("unused", backend(found, 0)),
+        ]);
+
+        let res = getter.get(req()).await.unwrap();
+        assert_eq!(res.content, vec![1]);
+        assert_eq!(res.backend, Some("legacy".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_get_falls_through_web_folder_not_found() {
+        // A web folder without any route answers every path with a 404.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, axum::Router::new()).await.unwrap() });
+
+        let origin = WebFolderGetter::new(
+            Url::new(&format!("http://{}", addr)).unwrap(),
+            None,
+            &NetworkConfig::default(),
+            None,
+        ).unwrap();
+        let getter = fallback(vec![("origin", Arc::new(origin)), ("bucket", backend(found, 1))]);
+
+        let res = getter.get(req()).await.unwrap();
+        assert_eq!(res.backend, Some("bucket".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_get_stops_on_client_error() {
+        let getter = fallback(vec![
+            ("bucket", backend(|| upstream(403), 1)),
+            ("legacy", backend(found, 0)),
+        ]);
+
+        let err = getter.get(req()).await.err().unwrap();
+        assert_eq!(err.status_code().as_u16(), 403);
+    }
+
+    #[tokio::test]
+    async fn test_get_exhausted() {
+        let getter = fallback(vec![
+            ("bucket", backend(|| upstream(502), 1)),
+            ("legacy", backend(not_found, 1)),
+        ]);
+
+        let err = getter.get(req()).await.err().unwrap();
+        assert!(matches!(err, Error::ObjectNotFound { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_get_stream_falls_through() {
+        let mut missing = MockGetter::new();
+        missing.expect_get_stream().times(1).returning(|_| Err(Error::ObjectNotFound { path: "a.jpg".to_string() }));
+        let mut legacy = MockGetter::new();
+        legacy.expect_get_stream().times(1).returning(|_| Ok(found().unwrap().into_stream()));
+
+        let getter = fallback(vec![("bucket", Arc::new(missing)), ("legacy", Arc::new(legacy))]);
+
+        let res = getter.get_stream(req()).await.unwrap();
+        assert_eq!(res.backend, Some("legacy".to_string()));
+    }
+}
@@ -0,0 +1,238 @@
+use std::io::{ErrorKind, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use async_trait::async_trait;
+use futures::{StreamExt, TryStreamExt};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, Take};
+use tokio_util::io::ReaderStream;
+use image::ImageFormat;
+use crate::storage::errors::Error;
+use crate::storage::getter::Getter;
+use crate::storage::{ByteStream, GetRequest, GetResponse};
+use crate::storage::types::Metadata;
+
+pub struct FileSystemGetter<'a> {
+    root: PathBuf,
+    path_prefix: Option<&'a str>,
+    max_bytes: Option<u64>,
+}
+
+impl<'a> FileSystemGetter<'a> {
+    /// `get` refuses files over `max_bytes`, streamed files are not limited.
+    pub fn new(root: PathBuf, path_prefix: Option<&'a str>, max_bytes: Option<u64>) -> std::io::Result<Self> {
+        Ok(FileSystemGetter {
+            root: root.canonicalize()?,
+            path_prefix,
+            max_bytes,
+        })
+    }
+
+    /// Resolves the request path against the root, refusing any path that leaves it either
+    /// lexically through `..` or by following a symlink.
+    async fn resolve(&self, path: &str) -> Result<PathBuf, Error> {
+        let relative = match self.path_prefix {
+            Some(prefix) => Path::new(prefix.trim_start_matches('/')).join(path.trim_start_matches('/')),
+            None => PathBuf::from(path.trim_start_matches('/')),
+        };
+
+        if relative.components().any(|c| !matches!(c, Component::Normal(_) | Component::CurDir)) {
+            return Err(Error::OutsideRoot { path: path.to_string() });
+        }
+
+        let resolved = tokio::fs::canonicalize(self.root.join(&relative))
+            .await
+            .map_err(|err| match err.kind() {
+                ErrorKind::NotFound => Error::ObjectNotFound { path: relative.display().to_string() },
+                _ => Error::IO(err),
+            })?;
+
+        if !resolved.starts_with(&self.root) {
+            return Err(Error::OutsideRoot { path: path.to_string() });
+        }
+
+        Ok(resolved)
+    }
+
+    /// Opens the requested object, positioned at the start of the requested range.
+    async fn open(&self, req: GetRequest) -> Result<GetResponse<Take<File>>, Error> {
+        let path = self.resolve(&req.path).await?;
+
+        let meta = tokio::fs::metadata(&path).await?;
+        if !meta.is_file() {
+            return Err(Error::ObjectNotFound { path: req.path });
+        }
+
+        let range = match req.options.as_ref().and_then(|o| o.range.as_ref()) {
+            Some(range) => range.resolve(meta.len())?,
+            None => None,
+        };
+        let (start, end) = range.unwrap_or((0, meta.len().saturating_sub(1)));
+        let len = if meta.len() == 0 { 0 } else { end - start + 1 };
+
+        let mut file = File::open(&path).await?;
+        file.seek(SeekFrom::Start(start)).await?;
+
+        Ok(GetResponse {
+            content: file.take(len),
+            metadata: Some(Metadata {
+                content_type: ImageFormat::from_path(&path)
+                    .ok()
+                    .map(|f| f.to_mime_type().to_string()),
+                last_modified: meta.modified().ok(),
+                cache_control: None,
+                etag: etag(&meta),
+                content_length: Some(len),
+            }),
+            cache_time: None,
+            backend: None,
+            content_range: range.map(|(start, end)| format!("bytes {}-{}/{}", start, end, meta.len())),
+            stale: false,
+        })
+    }
+}
+
+/// Weak validator built from the size and modification time of the file.
+fn etag(meta: &std::fs::Metadata) -> Option<String> {
+    let modified = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(format!("W/\"{:x}-{:x}\"", meta.len(), modified.as_nanos()))
+}
+
+#[async_trait]
+impl<'a> Getter for FileSystemGetter<'a> {
+    async fn get(&self, req: GetRequest) -> Result<GetResponse, Error> {
+        let mut res = self.open(req).await?;
+        // The file is read no further than its size when opened, even if it grows since.
+        if let Some(limit) = self.max_bytes.filter(|limit| res.content.limit() > *limit) {
+            return Err(Error::TooLarge { limit });
+        }
+
+        let mut content = Vec::new();
+        res.content.read_to_end(&mut content).await?;
+        Ok(res.map(|_| content))
+    }
+
+    async fn get_stream(&self, req: GetRequest) -> Result<GetResponse<ByteStream>, Error> {
+        Ok(self.open(req).await?.map(|file| ReaderStream::new(file).map_err(Error::IO).boxed()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::symlink;
+    use axum::http::StatusCode;
+    use tempfile::TempDir;
+    use crate::storage::types::{ByteRange, GetRequestOptions};
+    use super::*;
+
+    fn fixture() -> (TempDir, TempDir) {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("assets/cats")).unwrap();
+        fs::write(root.path().join("assets/cats/cat.png"), [1, 2, 3]).unwrap();
+
+        let outside = tempfile::tempdir().unwrap();
+        fs::write(outside.path().join("secret.png"), [4, 5, 6]).unwrap();
+        symlink(outside.path().join("secret.png"), root.path().join("assets/link.png")).unwrap();
+        symlink(root.path().join("assets/cats/cat.png"), root.path().join("assets/alias.png")).unwrap();
+
+        (root, outside)
+    }
+
+    fn req(path: &str) -> GetRequest {
+        GetRequest { path: path.to_string(), options: None }
+    }
+
+    #[tokio::test]
+    async fn test_get() {
+        let (root, _outside) = fixture();
+        let getter = FileSystemGetter::new(root.path().to_path_buf(), Some("/assets"), None).unwrap();
+
+        for path in ["cats/cat.png", "alias.png"] {
+            let res = getter.get(req(path)).await.unwrap();
+            assert_eq!(res.content, vec![1, 2, 3]);
+
+            let metadata = res.metadata.unwrap();
+            assert_eq!(metadata.content_type, Some("image/png".to_string()));
+            assert_eq!(
+                metadata.last_modified,
+                fs::metadata(root.path().join("assets/cats/cat.png")).unwrap().modified().ok()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_max_bytes() {
+        let (root, _outside) = fixture();
+        let getter = |max_bytes| FileSystemGetter::new(root.path().to_path_buf(), Some("/assets"), max_bytes).unwrap();
+
+        assert_eq!(getter(Some(3)).get(req("cats/cat.png")).await.unwrap().content, vec![1, 2, 3]);
+        let err = getter(Some(2)).get(req("cats/cat.png")).await.err().unwrap();
+        assert!(matches!(err, Error::TooLarge { limit: 2 }));
+        assert_eq!(err.status_code(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_get_range() {
+        let (root, _outside) = fixture();
+        let getter = FileSystemGetter::new(root.path().to_path_buf(), Some("/assets"), None).unwrap();
+        let req = |range: &str| GetRequest {
+            path: "cats/cat.png".to_string(),
+            options: Some(GetRequestOptions { range: Some(ByteRange::new(range)), ..GetRequestOptions::default() }),
+        };
+
+        let res = getter.get(req("bytes=1-")).await.unwrap();
+        assert_eq!(res.content, vec![2, 3]);
+        assert_eq!(res.content_range, Some("bytes 1-2/3".to_string()));
+
+        let res = getter.get(req("bytes=0-1,2-2")).await.unwrap();
+        assert_eq!(res.content, vec![1, 2, 3]);
+        assert_eq!(res.content_range, None);
+
+        let err = getter.get(req("bytes=3-")).await.err().unwrap();
+        assert_eq!(err.status_code(), StatusCode::RANGE_NOT_SATISFIABLE);
+    }
+
+    #[tokio::test]
+    async fn test_get_stream() {
+        let (root, _outside) = fixture();
+        let getter = FileSystemGetter::new(root.path().to_path_buf(), Some("/assets"), None).unwrap();
+
+        let res = getter.get_stream(GetRequest {
+            path: "cats/cat.png".to_string(),
+            options: Some(GetRequestOptions {
+                range: Some(ByteRange::new("bytes=-2")),
+                ..GetRequestOptions::default()
+            }),
+        }).await.unwrap();
+
+        assert_eq!(res.metadata.unwrap().content_length, Some(2));
+        assert_eq!(res.content_range, Some("bytes 1-2/3".to_string()));
+        let chunks: Vec<_> = res.content.try_collect().await.unwrap();
+        assert_eq!(chunks.concat(), vec![2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_get_not_found() {
+        let (root, _outside) = fixture();
+        let getter = FileSystemGetter::new(root.path().to_path_buf(), Some("/assets"), None).unwrap();
+
+        for path in ["cats/dog.png", "cats"] {
+            let err = getter.get(req(path)).await.err().unwrap();
+            assert!(matches!(err, Error::ObjectNotFound { .. }), "{}", path);
+            assert_eq!(err.status_code(), StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_outside_root() {
+        let (root, _outside) = fixture();
+        let getter = FileSystemGetter::new(root.path().join("assets"), None, None).unwrap();
+
+        for path in ["../assets/cats/cat.png", "cats/../../secret.png", "link.png"] {
+            let err = getter.get(req(path)).await.err().unwrap();
+            assert!(matches!(err, Error::OutsideRoot { .. }), "{}", path);
+            assert_eq!(err.status_code(), StatusCode::FORBIDDEN);
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use async_trait::async_trait;
 use crate::storage::errors::Error;
-use crate::storage::types::{GetRequest, GetResponse};
+use crate::storage::types::{ByteStream, GetRequest, GetResponse};
 
 #[cfg(test)]
 use mockall::{automock};
@@ -8,4 +8,14 @@ use mockall::{automock};
 #[async_trait]
 pub trait Getter {
     async fn get(&self, req: GetRequest) -> Result<GetResponse, Error>;
+
+    /// Returns the object as a stream of chunks rather than buffering it. Getters that cannot
+    /// stream fall back to a single chunk holding the response of `get`.
+    async fn get_stream(&self, req: GetRequest) -> Result<GetResponse<ByteStream>, Error> {
+        Ok(self.get(req).await?.into_stream())
+    }
+
+    /// Drops whatever the getter keeps of the objects covered by the surrogate `key`, see
+    /// `surrogate::covers`. Getters wrapping others pass the purge on to them.
+    async fn purge(&self, _key: &str) {}
 }
@@ -1,6 +1,16 @@
 pub mod getter;
+pub(crate) mod client;
 pub(crate) mod errors;
 mod types;
 pub(crate) mod webfolder;
+pub(crate) mod s3;
+pub(crate) mod filesystem;
+pub(crate) mod routing;
+pub(crate) mod fallback;
+pub(crate) mod retry;
+pub(crate) mod breaker;
+pub(crate) mod coalesce;
+pub(crate) mod cache;
+pub(crate) mod stale;
 pub(crate) use getter::Getter;
-pub(crate) use types::{GetRequest, GetRequestOptions, GetResponse};
+pub(crate) use types::{cache_time, ByteRange, ByteStream, GetRequest, GetRequestOptions, GetResponse, Metadata};
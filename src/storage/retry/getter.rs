@@ -0,0 +1,293 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::{Counter, Meter};
+use rand::Rng;
+use tokio::time::{Instant, timeout};
+use crate::config::Retry;
+use crate::storage::errors::Error;
+use crate::storage::getter::Getter;
+use crate::storage::{ByteStream, GetRequest, GetResponse};
+
+/// Retries transient failures of the wrapped getter with exponential backoff, giving up
+/// once the attempts or the deadline run out.
+pub struct RetryGetter {
+    inner: Arc<dyn Getter + Send + Sync>,
+    policy: Retry,
+    deadline: Duration,
+    counter: Counter<u64>,
+    attributes: [KeyValue; 1],
+}
+
+impl RetryGetter {
+    /// `attempt_timeout` is the timeout of a single attempt, the deadline defaults to enough
+    /// time for every attempt to run into it, plus the longest backoff between them.
+    pub fn new(
+        name: String,
+        inner: Arc<dyn Getter + Send + Sync>,
+        policy: Retry,
+        attempt_timeout: Duration,
+        meter: Arc<Meter>,
+    ) -> Self {
+        let retries = policy.max_attempts.saturating_sub(1);
+        let deadline = attempt_timeout.saturating_mul(policy.max_attempts.max(1))
+            .saturating_add(policy.max_delay.saturating_mul(retries));
+
+        Self {
+            inner,
+            deadline: policy.deadline.unwrap_or(deadline),
+            policy,
+            counter: meter.u64_counter("storage_retries")
+                .with_description("Number of retried storage requests")
+                .init(),
+            attributes: [KeyValue::new("source", name)],
+        }
+    }
+
+    async fn retry<C, F, Fut>(&self, req: GetRequest, get: F) -> Result<GetResponse<C>, Error>
+    where
+        F: Fn(Arc<dyn Getter + Send + Sync>, GetRequest) -> Fut,
+        Fut: Future<Output=Result<GetResponse<C>, Error>>,
+    {
+        let deadline = Instant::now() + self.deadline;
+        let mut attempt = 1;
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let err = match timeout(remaining, get(self.inner.clone(), req.clone())).await {
+                Ok(Ok(res)) => return Ok(res),
+                Ok(Err(err)) => err,
+                Err(_) => return Err(Error::Upstream {
+                    status_code: Some(504),
+                    message: format!("deadline of {:?} exceeded after {} attempt(s)", self.deadline, attempt),
+                }),
+            };
+
+            if attempt >= self.policy.max_attempts || !self.is_retryable(&err) {
+                return Err(err);
+            }
+
+            let delay = self.backoff(attempt);
+            if Instant::now() + delay >= deadline {
+                return Err(err);
+            }
+
+            tokio::time::sleep(delay).await;
+            self.counter.add(1, &self.attributes);
+            attempt += 1;
+        }
+    }
+
+    fn is_retryable(&self, err: &Error) -> bool {
+        match err {
+            Error::Upstream { status_code: Some(code), .. } => self.policy.retryable_status_codes.contains(code),
+            Error::Upstream { status_code: None, .. } | Error::Reqwest(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns the delay before the given retry, `retry` starting at 1.
+    fn backoff(&self, retry: u32) -> Duration {
+        let delay = self.policy.base_delay
+            .saturating_mul(2u32.saturating_pow(retry - 1))
+            .min(self.policy.max_delay);
+
+        if self.policy.jitter {
+            delay.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+        } else {
+            delay
+        }
+    }
+}
+
+#[async_trait]
+impl Getter for RetryGetter {
+    async fn get(&self, req: GetRequest) -> Result<GetResponse, Error> {
+        self.retry(req, |inner, req| async move { inner.get(req).await }).await
+    }
+
+    /// Only the request up to the start of the body is retried; errors while streaming the
+    /// body are passed on as they are.
+    async fn get_stream(&self, req: GetRequest) -> Result<GetResponse<ByteStream>, Error> {
+        self.retry(req, |inner, req| async move { inner.get_stream(req).await }).await
+    }
+
+    async fn purge(&self, key: &str) {
+        self.inner.purge(key).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use opentelemetry::metrics::MeterProvider;
+    use crate::storage::getter::MockGetter;
+    use super::*;
+
+    fn policy(max_attempts: u32, base_delay: Duration) -> Retry {
+        Retry {
+            max_attempts,
+            base_delay,
+            max_delay: Duration::from_millis(20),
+            jitter: true,
+            retryable_status_codes: vec![502, 503, 504],
+            deadline: None,
+        }
+    }
+
+    fn getter(inner: MockGetter, policy: Retry, attempt_timeout: Duration) -> RetryGetter {
+        RetryGetter::new(
+            "origin".to_string(),
+            Arc::new(inner),
+            policy,
+            attempt_timeout,
+            Arc::new(opentelemetry::global::meter_provider().meter("test-meter")),
+        )
+    }
+
+    /// Fails with the given errors in order and succeeds afterwards.
+    fn flaky(failures: Vec<Option<u16>>) -> (MockGetter, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let mut mock = MockGetter::new();
+        mock.expect_get().returning(move |_| {
+            match failures.get(counter.fetch_add(1, Ordering::SeqCst)) {
+                Some(status_code) => Err(Error::Upstream { status_code: *status_code, message: "".to_string() }),
+                None => Ok(GetResponse { content: vec![1], ..GetResponse::default() }),
+            }
+        });
+        (mock, calls)
+    }
+
+    fn req() -> GetRequest {
+        GetRequest { path: "a.jpg".to_string(), options: None }
+    }
+
+    #[tokio::test]
+    async fn test_get_retries_transient_errors() {
+        let (mock, calls) = flaky(vec![Some(502), None, Some(504)]);
+        let res = getter(mock, policy(4, Duration::from_millis(1)), Duration::from_secs(1))
+            .get(req())
+            .await
+            .unwrap();
+
+        assert_eq!(res.content, vec![1]);
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_get_gives_up_after_max_attempts() {
+        let (mock, calls) = flaky(vec![Some(503), Some(503), Some(503)]);
+        let err = getter(mock, policy(2, Duration::from_millis(1)), Duration::from_secs(1))
+            .get(req())
+            .await
+            .err()
+            .unwrap();
+
+        assert_eq!(err.status_code().as_u16(), 503);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_get_does_not_retry_other_errors() {
+        let (mock, calls) = flaky(vec![Some(500)]);
+        let err = getter(mock, policy(3, Duration::from_millis(1)), Duration::from_secs(1))
+            .get(req())
+            .await
+            .err()
+            .unwrap();
+
+        assert_eq!(err.status_code().as_u16(), 500);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_get_respects_deadline() {
+        let (mock, calls) = flaky(vec![Some(503), Some(503)]);
+        let mut policy = policy(3, Duration::from_millis(50));
+        policy.max_delay = Duration::from_millis(50);
+        policy.jitter = false;
+        policy.deadline = Some(Duration::from_millis(20));
+
+        let err = getter(mock, policy, Duration::from_secs(1))
+            .get(req())
+            .await
+            .err()
+            .unwrap();
+
+        assert_eq!(err.status_code().as_u16(), 503);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    struct Slow;
+
+    #[async_trait]
+    impl Getter for Slow {
+        async fn get(&self, _: GetRequest) -> Result<GetResponse, Error> {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(GetResponse::default())
+        }
+    }
+
+    /// Fails its first attempt once the attempt timeout has passed, like a client timing out.
+    struct TimesOutOnce {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Getter for TimesOutOnce {
+        async fn get(&self, _: GetRequest) -> Result<GetResponse, Error> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                return Err(Error::Upstream { status_code: None, message: "timed out".to_string() });
+            }
+            Ok(GetResponse { content: vec![1], ..GetResponse::default() })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_retries_timed_out_attempt() {
+        let inner = Arc::new(TimesOutOnce { calls: AtomicUsize::new(0) });
+        let getter = RetryGetter::new(
+            "origin".to_string(),
+            inner.clone(),
+            policy(3, Duration::from_millis(1)),
+            Duration::from_millis(20),
+            Arc::new(opentelemetry::global::meter_provider().meter("test-meter")),
+        );
+
+        let res = getter.get(req()).await.unwrap();
+        assert_eq!(res.content, vec![1]);
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 2);
+        assert_eq!(getter.deadline, Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn test_get_times_out() {
+        let getter = RetryGetter::new(
+            "origin".to_string(),
+            Arc::new(Slow),
+            policy(3, Duration::from_millis(1)),
+            Duration::from_millis(20),
+            Arc::new(opentelemetry::global::meter_provider().meter("test-meter")),
+        );
+
+        let err = getter.get(req()).await.err().unwrap();
+        assert_eq!(err.status_code().as_u16(), 504);
+    }
+
+    #[test]
+    fn test_backoff() {
+        let mut policy = policy(5, Duration::from_millis(5));
+        policy.jitter = false;
+        let getter = getter(MockGetter::new(), policy, Duration::from_secs(1));
+
+        assert_eq!(getter.backoff(1), Duration::from_millis(5));
+        assert_eq!(getter.backoff(2), Duration::from_millis(10));
+        assert_eq!(getter.backoff(3), Duration::from_millis(20));
+        assert_eq!(getter.backoff(4), Duration::from_millis(20));
+        assert_eq!(getter.backoff(40), Duration::from_millis(20));
+    }
+}
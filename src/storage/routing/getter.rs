@@ -0,0 +1,116 @@
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use glob::PatternError;
+use crate::storage::errors::Error;
+use crate::storage::getter::Getter;
+use crate::storage::routing::matcher::Matcher;
+use crate::storage::{ByteStream, GetRequest, GetResponse};
+
+pub struct Route {
+    matcher: Matcher,
+    getter: Arc<dyn Getter + Send + Sync>,
+    cache_time: Option<Duration>,
+}
+
+impl Route {
+    pub fn new(
+        pattern: Option<&str>,
+        getter: Arc<dyn Getter + Send + Sync>,
+        cache_time: Option<Duration>,
+    ) -> Result<Self, PatternError> {
+        Ok(Route { matcher: Matcher::new(pattern)?, getter, cache_time })
+    }
+}
+
+/// Sends each request to the first route whose pattern matches its path.
+pub struct RoutingGetter {
+    routes: Vec<Route>,
+}
+
+impl RoutingGetter {
+    pub fn new(routes: Vec<Route>) -> Self {
+        RoutingGetter { routes }
+    }
+
+    fn route(&self, path: &str) -> Result<&Route, Error> {
+        self.routes
+            .iter()
+            .find(|r| r.matcher.matches(path))
+            .ok_or_else(|| Error::ObjectNotFound { path: path.to_string() })
+    }
+}
+
+#[async_trait]
+impl Getter for RoutingGetter {
+    async fn get(&self, req: GetRequest) -> Result<GetResponse, Error> {
+        let route = self.route(&req.path)?;
+
+        let mut res = route.getter.get(req).await?;
+        res.cache_time = res.cache_time.or(route.cache_time);
+        Ok(res)
+    }
+
+    async fn get_stream(&self, req: GetRequest) -> Result<GetResponse<ByteStream>, Error> {
+        let route = self.route(&req.path)?;
+
+        let mut res = route.getter.get_stream(req).await?;
+        res.cache_time = res.cache_time.or(route.cache_time);
+        Ok(res)
+    }
+
+    async fn purge(&self, key: &str) {
+        for route in &self.routes {
+            route.getter.purge(key).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockall::predicate::*;
+    use crate::storage::getter::MockGetter;
+    use super::*;
+
+    fn getter(path: &'static str, content: Vec<u8>) -> Arc<dyn Getter + Send + Sync> {
+        let mut mock = MockGetter::new();
+        mock.expect_get()
+            .with(eq(GetRequest { path: path.to_string(), options: None }))
+            .returning(move |_| Ok(GetResponse { content: content.clone(), ..GetResponse::default() }));
+        Arc::new(mock)
+    }
+
+    fn req(path: &str) -> GetRequest {
+        GetRequest { path: path.to_string(), options: None }
+    }
+
+    #[tokio::test]
+    async fn test_get() {
+        let router = RoutingGetter::new(vec![
+            Route::new(Some("/avatars/*"), getter("avatars/a.jpg", vec![1]), Some(Duration::from_secs(60))).unwrap(),
+            Route::new(Some("/static"), getter("static/b.jpg", vec![2]), None).unwrap(),
+            Route::new(None, getter("c.jpg", vec![3]), None).unwrap(),
+        ]);
+
+        let res = router.get(req("avatars/a.jpg")).await.unwrap();
+        assert_eq!(res.content, vec![1]);
+        assert_eq!(res.cache_time, Some(Duration::from_secs(60)));
+
+        let res = router.get(req("static/b.jpg")).await.unwrap();
+        assert_eq!(res.content, vec![2]);
+        assert_eq!(res.cache_time, None);
+
+        let res = router.get(req("c.jpg")).await.unwrap();
+        assert_eq!(res.content, vec![3]);
+    }
+
+    #[tokio::test]
+    async fn test_get_no_route() {
+        let router = RoutingGetter::new(vec![
+            Route::new(Some("/avatars/*"), Arc::new(MockGetter::new()), None).unwrap(),
+        ]);
+
+        let err = router.get(req("static/b.jpg")).await.err().unwrap();
+        assert!(matches!(err, Error::ObjectNotFound { .. }));
+    }
+}
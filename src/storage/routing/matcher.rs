@@ -0,0 +1,80 @@
+use glob::{Pattern, PatternError};
+
+/// Matches request paths against a source's `pattern`. Patterns containing glob
+/// metacharacters are matched as globs, anything else as a path prefix.
+#[derive(Debug)]
+pub enum Matcher {
+    Any,
+    Prefix(String),
+    Glob(Pattern),
+}
+
+impl Matcher {
+    pub fn new(pattern: Option<&str>) -> Result<Self, PatternError> {
+        let pattern = match pattern.map(normalize) {
+            None => return Ok(Matcher::Any),
+            Some(p) if p == "/" => return Ok(Matcher::Any),
+            Some(p) => p,
+        };
+
+        if pattern.contains(['*', '?', '[']) {
+            Ok(Matcher::Glob(Pattern::new(&pattern)?))
+        } else {
+            Ok(Matcher::Prefix(pattern.trim_end_matches('/').to_string()))
+        }
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        let path = normalize(path);
+        match self {
+            Matcher::Any => true,
+            Matcher::Prefix(prefix) => {
+                path.strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            }
+            Matcher::Glob(pattern) => pattern.matches(&path),
+        }
+    }
+}
+
+fn normalize(path: &str) -> String {
+    format!("/{}", path.trim_start_matches('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        let testcases = vec![
+            (None, "avatars/a.jpg", true),
+            (Some("/"), "avatars/a.jpg", true),
+            (Some("/avatars"), "avatars/a.jpg", true),
+            (Some("avatars/"), "/avatars/a.jpg", true),
+            (Some("/avatars"), "avatars", true),
+            (Some("/avatars"), "avatarsx/a.jpg", false),
+            (Some("/avatars"), "static/a.jpg", false),
+            (Some("/avatars/*"), "avatars/a.jpg", true),
+            (Some("/avatars/*"), "avatars/2024/a.jpg", true),
+            (Some("/avatars/*"), "static/a.jpg", false),
+            (Some("/*.png"), "a.png", true),
+            (Some("/*.png"), "a.jpg", false),
+            (Some("/static/[ab]?.gif"), "static/a1.gif", true),
+            (Some("/static/[ab]?.gif"), "static/c1.gif", false),
+        ];
+
+        for (pattern, path, expected) in testcases {
+            assert_eq!(
+                Matcher::new(pattern).unwrap().matches(path),
+                expected,
+                "{:?} matching {}", pattern, path,
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        assert!(Matcher::new(Some("/avatars/[")).is_err());
+    }
+}
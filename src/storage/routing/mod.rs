@@ -0,0 +1,4 @@
+pub(crate) mod getter;
+mod matcher;
+
+pub use getter::{Route, RoutingGetter};
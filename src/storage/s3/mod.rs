@@ -0,0 +1,4 @@
+pub(crate) mod getter;
+mod signer;
+
+pub use getter::S3Getter;
@@ -0,0 +1,250 @@
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use async_trait::async_trait;
+use lru::LruCache;
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::{Counter, Meter};
+use tokio::time::timeout;
+use crate::config::StaleIfError;
+use crate::surrogate;
+use crate::storage::errors::Error;
+use crate::storage::getter::Getter;
+use crate::storage::{ByteStream, GetRequest, GetResponse};
+
+struct Store {
+    entries: LruCache<String, GetResponse>,
+    bytes: usize,
+}
+
+/// Remembers the last successfully fetched copy of each object and serves it, marked as
+/// stale, while the wrapped getter fails or takes longer than `timeout`. Streamed responses
+/// are never buffered, so only `get` records copies, though both methods can serve them.
+pub struct StaleGetter {
+    inner: Arc<dyn Getter + Send + Sync>,
+    cfg: StaleIfError,
+    store: Mutex<Store>,
+    counter: Counter<u64>,
+    attributes: [KeyValue; 1],
+}
+
+impl StaleGetter {
+    pub fn new(name: String, inner: Arc<dyn Getter + Send + Sync>, cfg: StaleIfError, meter: Arc<Meter>) -> Self {
+        Self {
+            inner,
+            cfg,
+            store: Mutex::new(Store { entries: LruCache::unbounded(), bytes: 0 }),
+            counter: meter.u64_counter("storage_stale_responses")
+                .with_description("Number of stale objects served because the source failed")
+                .init(),
+            attributes: [KeyValue::new("source", name)],
+        }
+    }
+
+    fn stale(&self, req: &GetRequest) -> Option<GetResponse> {
+        if req.options.is_some() {
+            return None;
+        }
+        self.store.lock().unwrap().entries.get(&req.path).cloned()
+    }
+
+    fn remember(&self, req: &GetRequest, res: &GetResponse) {
+        let size = res.content.len();
+        if req.options.is_some() || size > self.cfg.max_bytes {
+            return;
+        }
+
+        let mut store = self.store.lock().unwrap();
+        if let Some(replaced) = store.entries.put(req.path.clone(), res.clone()) {
+            store.bytes -= replaced.content.len();
+        }
+        store.bytes += size;
+
+        while store.bytes > self.cfg.max_bytes {
+            let Some((_, evicted)) = store.entries.pop_lru() else { break };
+            store.bytes -= evicted.content.len();
+        }
+    }
+
+    /// Runs `fut`, falling back to the stale copy of the object when it fails with a server
+    /// error or, if there is a copy to fall back to, does not complete within the timeout.
+    async fn fallback<C>(
+        &self,
+        req: &GetRequest,
+        fut: impl Future<Output = Result<GetResponse<C>, Error>>,
+        convert: impl FnOnce(GetResponse) -> GetResponse<C>,
+    ) -> Result<GetResponse<C>, Error> {
+        let stale = self.stale(req);
+
+        let res = match (self.cfg.timeout, &stale) {
+            (Some(limit), Some(_)) => timeout(limit, fut).await.unwrap_or_else(|_| Err(Error::Upstream {
+                status_code: Some(504),
+                message: format!("timed out after {:?}", limit),
+            })),
+            _ => fut.await,
+        };
+
+        match (res, stale) {
+            (Err(err), Some(stale)) if Self::is_failure(&err) => {
+                self.counter.add(1, &self.attributes);
+                Ok(GetResponse { stale: true, ..convert(stale) })
+            }
+            (res, _) => res,
+        }
+    }
+
+    fn is_failure(err: &Error) -> bool {
+        match err {
+            Error::ObjectNotFound { .. } | Error::OutsideRoot { .. } => false,
+            _ => err.status_code().is_server_error(),
+        }
+    }
+}
+
+#[async_trait]
+impl Getter for StaleGetter {
+    async fn get(&self, req: GetRequest) -> Result<GetResponse, Error> {
+        let res = self.fallback(&req, self.inner.get(req.clone()), |res| res).await?;
+        if !res.stale {
+            self.remember(&req, &res);
+        }
+        Ok(res)
+    }
+
+    async fn get_stream(&self, req: GetRequest) -> Result<GetResponse<ByteStream>, Error> {
+        self.fallback(&req, self.inner.get_stream(req.clone()), GetResponse::into_stream).await
+    }
+
+    /// Purged objects lose their stale copies, so that they cannot come back while the source
+    /// fails.
+    async fn purge(&self, key: &str) {
+        {
+            let mut store = self.store.lock().unwrap();
+            let purged = store.entries.iter()
+                .filter(|(path, _)| surrogate::covers(key, path))
+                .map(|(path, _)| path.clone())
+                .collect::<Vec<_>>();
+            for path in purged {
+                if let Some(res) = store.entries.pop(&path) {
+                    store.bytes -= res.content.len();
+                }
+            }
+        }
+        self.inner.purge(key).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+    use opentelemetry::metrics::MeterProvider;
+    use crate::storage::{ByteRange, GetRequestOptions};
+    use super::*;
+
+    /// Serves the path as content, failing with a 502 while `failing` is set and taking a
+    /// second while `slow` is set.
+    #[derive(Default)]
+    struct Origin {
+        failing: AtomicBool,
+        slow: AtomicBool,
+    }
+
+    #[async_trait]
+    impl Getter for Origin {
+        async fn get(&self, req: GetRequest) -> Result<GetResponse, Error> {
+            if self.slow.load(Ordering::SeqCst) {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            match req.path.as_str() {
+                "missing.jpg" => Err(Error::ObjectNotFound { path: req.path }),
+                _ if self.failing.load(Ordering::SeqCst) => Err(Error::Upstream { status_code: Some(502), message: "".to_string() }),
+                path => Ok(GetResponse { content: path.as_bytes().to_vec(), ..GetResponse::default() }),
+            }
+        }
+    }
+
+    fn getter(timeout: Option<Duration>) -> (StaleGetter, Arc<Origin>) {
+        let origin = Arc::new(Origin::default());
+        let getter = StaleGetter::new(
+            "test".to_string(),
+            origin.clone(),
+            StaleIfError { max_bytes: 8, timeout },
+            Arc::new(opentelemetry::global::meter_provider().meter("test-meter")),
+        );
+        (getter, origin)
+    }
+
+    fn req(path: &str) -> GetRequest {
+        GetRequest { path: path.to_string(), options: None }
+    }
+
+    #[tokio::test]
+    async fn test_get_serves_stale_on_error() {
+        let (getter, origin) = getter(None);
+        let res = getter.get(req("a.jpg")).await.unwrap();
+        assert!(!res.stale);
+
+        origin.failing.store(true, Ordering::SeqCst);
+        let res = getter.get(req("a.jpg")).await.unwrap();
+        assert_eq!(res.content, b"a.jpg");
+        assert!(res.stale);
+
+        let res = getter.get_stream(req("a.jpg")).await.unwrap();
+        assert!(res.stale);
+
+        // Other objects and ranged requests have no stale copy to fall back to.
+        assert!(getter.get(req("b.jpg")).await.is_err());
+        let ranged = GetRequest {
+            options: Some(GetRequestOptions { range: Some(ByteRange::new("bytes=0-1")), ..GetRequestOptions::default() }),
+            ..req("a.jpg")
+        };
+        assert!(getter.get(ranged).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_get_passes_client_errors() {
+        let (getter, _) = getter(None);
+
+        for _ in 0..2 {
+            let err = getter.get(req("missing.jpg")).await.err().unwrap();
+            assert_eq!(err.status_code(), 404);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_serves_stale_on_timeout() {
+        let (getter, origin) = getter(Some(Duration::from_millis(50)));
+        getter.get(req("a.jpg")).await.unwrap();
+
+        origin.slow.store(true, Ordering::SeqCst);
+        let res = getter.get(req("a.jpg")).await.unwrap();
+        assert!(res.stale);
+    }
+
+    #[tokio::test]
+    async fn test_purge() {
+        let (getter, origin) = getter(None);
+        getter.get(req("a/1.jpg")).await.unwrap();
+        getter.get(req("b/1.jpg")).await.unwrap();
+
+        getter.purge("a").await;
+
+        origin.failing.store(true, Ordering::SeqCst);
+        assert!(getter.get(req("a/1.jpg")).await.is_err());
+        assert!(getter.get(req("b/1.jpg")).await.unwrap().stale);
+        assert_eq!(getter.store.lock().unwrap().bytes, 7);
+    }
+
+    #[tokio::test]
+    async fn test_store_is_bounded() {
+        let (getter, origin) = getter(None);
+        getter.get(req("a.jpg")).await.unwrap();
+        getter.get(req("b.jpg")).await.unwrap();
+        getter.get(req("toolarge.jpg")).await.unwrap();
+
+        origin.failing.store(true, Ordering::SeqCst);
+        assert!(getter.get(req("a.jpg")).await.is_err());
+        assert!(getter.get(req("b.jpg")).await.unwrap().stale);
+        assert!(getter.get(req("toolarge.jpg")).await.is_err());
+    }
+}
@@ -1,29 +1,209 @@
 use std::fmt::Debug;
 use std::time::{Duration, SystemTime};
+use axum::http::header::{CACHE_CONTROL, CONTENT_LENGTH, CONTENT_TYPE, ETAG, LAST_MODIFIED};
+use axum::body::Bytes;
+use axum::http::HeaderMap;
+use futures::future;
+use futures::stream::{self, BoxStream, StreamExt};
+use crate::config::UpstreamMaxAge;
+use crate::storage::errors::Error;
 
-#[derive(Debug, PartialEq)]
+/// Value of a `Range` request header, e.g. `bytes=0-1023`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub struct ByteRange(String);
 
-#[derive(Debug, PartialEq)]
+impl ByteRange {
+    pub fn new(value: impl Into<String>) -> Self {
+        ByteRange(value.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Resolves a single `bytes` range against an object of `len` bytes into inclusive
+    /// offsets. Ranges that cannot be parsed, including multi-range requests, resolve to
+    /// `None` so that the whole object is served instead.
+    pub fn resolve(&self, len: u64) -> Result<Option<(u64, u64)>, Error> {
+        let spec = match self.0.trim().strip_prefix("bytes=") {
+            Some(spec) if !spec.contains(',') => spec,
+            _ => return Ok(None),
+        };
+        let Some((start, end)) = spec.split_once('-') else { return Ok(None) };
+        let unsatisfiable = || Error::RangeNotSatisfiable { range: self.0.clone() };
+
+        let (start, end) = match (start.trim(), end.trim()) {
+            ("", suffix) => match suffix.parse::<u64>() {
+                Ok(0) => return Err(unsatisfiable()),
+                Ok(suffix) => (len.saturating_sub(suffix), len.saturating_sub(1)),
+                Err(_) => return Ok(None),
+            },
+            (start, end) => match (start.parse::<u64>(), end) {
+                (Ok(start), "") => (start, len.saturating_sub(1)),
+                (Ok(start), end) => match end.parse::<u64>() {
+                    Ok(end) if end >= start => (start, end.min(len.saturating_sub(1))),
+                    _ => return Ok(None),
+                },
+                _ => return Ok(None),
+            },
+        };
+
+        if len == 0 || start >= len {
+            return Err(unsatisfiable());
+        }
+        Ok(Some((start, end)))
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone)]
 pub struct GetRequestOptions {
-    pub range: ByteRange,
+    pub range: Option<ByteRange>,
+    /// Conditional headers forwarded to origins that support them.
+    pub if_none_match: Option<String>,
+    pub if_modified_since: Option<String>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub struct GetRequest {
     pub path: String,
     pub options: Option<GetRequestOptions>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Default, PartialEq, Clone)]
 pub struct Metadata {
     pub content_type: Option<String>,
     pub last_modified: Option<SystemTime>,
     pub cache_control: Option<Duration>,
+    pub etag: Option<String>,
+    pub content_length: Option<u64>,
+}
+
+impl From<&HeaderMap> for Metadata {
+    fn from(headers: &HeaderMap) -> Self {
+        let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+
+        Metadata {
+            content_type: header(CONTENT_TYPE).map(|v| v.to_string()),
+            last_modified: header(LAST_MODIFIED)
+                .and_then(|v| httpdate::parse_http_date(v).ok()),
+            cache_control: header(CACHE_CONTROL).and_then(max_age),
+            etag: header(ETAG).map(|v| v.to_string()),
+            content_length: header(CONTENT_LENGTH).and_then(|v| v.parse().ok()),
+        }
+    }
+}
+
+fn max_age(cache_control: &str) -> Option<Duration> {
+    cache_control
+        .split(',')
+        .filter_map(|directive| directive.trim().split_once('='))
+        .find(|(name, _)| name.eq_ignore_ascii_case("max-age"))
+        .and_then(|(_, value)| value.trim_matches('"').parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
+
+/// Applies the max-age sent by the origin to the configured cache duration.
+pub fn cache_time(cache_time: Duration, metadata: Option<&Metadata>, policy: UpstreamMaxAge) -> Duration {
+    let max_age = metadata.and_then(|m| m.cache_control);
+
+    match (policy, max_age) {
+        (UpstreamMaxAge::Override, Some(max_age)) => max_age,
+        (UpstreamMaxAge::Cap, Some(max_age)) => cache_time.min(max_age),
+        _ => cache_time,
+    }
 }
 
-#[derive(PartialEq)]
-pub struct GetResponse {
-    pub content: Vec<u8>,
+/// Body of a streamed object.
+pub type ByteStream = BoxStream<'static, Result<Bytes, Error>>;
+
+#[derive(PartialEq, Default, Clone)]
+pub struct GetResponse<C = Vec<u8>> {
+    pub content: C,
     pub metadata: Option<Metadata>,
+    /// Overrides the handler's cache duration for this response.
+    pub cache_time: Option<Duration>,
+    /// Name of the backend that served the object, when the source is composed of several.
+    pub backend: Option<String>,
+    /// `Content-Range` of a partial response to a ranged request.
+    pub content_range: Option<String>,
+    /// Set when the origin failed and a previously fetched copy is served instead.
+    pub stale: bool,
+}
+
+impl<C> GetResponse<C> {
+    pub fn map<D>(self, f: impl FnOnce(C) -> D) -> GetResponse<D> {
+        GetResponse {
+            content: f(self.content),
+            metadata: self.metadata,
+            cache_time: self.cache_time,
+            backend: self.backend,
+            content_range: self.content_range,
+            stale: self.stale,
+        }
+    }
+}
+
+impl GetResponse {
+    /// Turns a buffered response into a stream of a single chunk.
+    pub fn into_stream(self) -> GetResponse<ByteStream> {
+        self.map(|content| stream::once(future::ok(Bytes::from(content))).boxed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+    use super::*;
+
+    #[test]
+    fn test_metadata_from_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("image/png"));
+        headers.insert(LAST_MODIFIED, HeaderValue::from_static("Fri, 24 May 2013 00:00:00 GMT"));
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static("public, s-maxage=10, max-age=3600"));
+        headers.insert(ETAG, HeaderValue::from_static("\"5d8c72a5edda8\""));
+        headers.insert(CONTENT_LENGTH, HeaderValue::from_static("1024"));
+
+        assert_eq!(Metadata::from(&headers), Metadata {
+            content_type: Some("image/png".to_string()),
+            last_modified: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1369353600)),
+            cache_control: Some(Duration::from_secs(3600)),
+            etag: Some("\"5d8c72a5edda8\"".to_string()),
+            content_length: Some(1024),
+        });
+    }
+
+    #[test]
+    fn test_byte_range_resolve() {
+        let testcases = vec![
+            ("bytes=0-99", Ok(Some((0, 99)))),
+            ("bytes=100-", Ok(Some((100, 999)))),
+            ("bytes=-100", Ok(Some((900, 999)))),
+            ("bytes=-5000", Ok(Some((0, 999)))),
+            ("bytes=990-5000", Ok(Some((990, 999)))),
+            ("bytes=0-0", Ok(Some((0, 0)))),
+            ("bytes=1000-", Err(())),
+            ("bytes=-0", Err(())),
+            ("bytes=0-1,5-9", Ok(None)),
+            ("bytes=9-5", Ok(None)),
+            ("bytes=a-b", Ok(None)),
+            ("items=0-9", Ok(None)),
+        ];
+
+        for (range, expected) in testcases {
+            let resolved = ByteRange::new(range).resolve(1000).map_err(|_| ());
+            assert_eq!(resolved, expected, "{}", range);
+        }
+    }
+
+    #[test]
+    fn test_metadata_from_empty_headers() {
+        assert_eq!(Metadata::from(&HeaderMap::new()), Metadata {
+            content_type: None,
+            last_modified: None,
+            cache_control: None,
+            etag: None,
+            content_length: None,
+        });
+    }
 }
@@ -1,61 +1,211 @@
 use std::sync::Arc;
 use async_trait::async_trait;
+use axum::http::header::{IF_MODIFIED_SINCE, IF_NONE_MATCH, RANGE};
 use axum::http::StatusCode;
 use reqwest::Client;
+use crate::config::NetworkConfig;
 use crate::config::url::Url;
+use crate::storage::client;
 use crate::storage::errors::Error;
 use crate::storage::getter::Getter;
-use crate::storage::types::{GetRequest, GetResponse};
+use crate::storage::types::{ByteStream, GetRequest, GetResponse, Metadata};
 
 pub struct WebFolderGetter<'a> {
     base_url: Url,
     path_prefix: Option<&'a str>,
     client: Arc<Client>,
+    max_bytes: Option<u64>,
 }
 
 impl<'a> WebFolderGetter<'a> {
-    pub fn new(base_url: Url, path_prefix: Option<&'a str>) -> Self {
-        WebFolderGetter {
+    /// `get` refuses objects over `max_bytes`, streamed objects are not limited.
+    pub fn new(
+        base_url: Url,
+        path_prefix: Option<&'a str>,
+        network: &NetworkConfig,
+        max_bytes: Option<u64>,
+    ) -> Result<Self, Error> {
+        Ok(WebFolderGetter {
             base_url,
             path_prefix,
-            client: Arc::new(Client::new()),
-        }
+            client: Arc::new(client::new(network)?),
+            max_bytes,
+        })
     }
 }
 
-#[async_trait]
-impl<'a> Getter for WebFolderGetter<'a> {
-    async fn get(&self, req: GetRequest) -> Result<GetResponse, Error> {
-        let url = format!(
-            "{}://{}{}",
-            self.base_url.scheme(),
-            self.base_url.host_str().unwrap_or(""),
-            if let Some(prefix) = self.path_prefix {
-                format!("{}/{}", prefix, req.path)
-            } else {
-                req.path
+impl<'a> WebFolderGetter<'a> {
+    async fn send(&self, req: GetRequest) -> Result<reqwest::Response, Error> {
+        let url = self.base_url.join_path(self.path_prefix, &req.path)
+            .map_err(|err| Error::Upstream { status_code: None, message: err.to_string() })?;
+
+        let mut builder = self.client.get(url.as_str());
+        if let Some(options) = &req.options {
+            let headers = [
+                (RANGE, options.range.as_ref().map(|r| r.as_str())),
+                (IF_NONE_MATCH, options.if_none_match.as_deref()),
+                (IF_MODIFIED_SINCE, options.if_modified_since.as_deref()),
+            ];
+            for (name, value) in headers {
+                if let Some(value) = value {
+                    builder = builder.header(name, value);
+                }
             }
-        );
+        }
 
-        let res = self.client.get(&url).send().await.map_err(|err| {
+        let res = builder.send().await.map_err(|err| {
             Error::Upstream {
                 status_code: err.status().map(|s| s.as_u16()),
                 message: err.to_string(),
             }
         })?;
 
-        if res.status() != StatusCode::OK {
-            return Err(Error::Upstream {
-                status_code: Some(res.status().as_u16()),
-                message: res.text().await.unwrap_or_default(),
-            });
+        match res.status() {
+            StatusCode::OK | StatusCode::PARTIAL_CONTENT => {}
+            StatusCode::NOT_FOUND => return Err(Error::ObjectNotFound { path: req.path }),
+            StatusCode::NOT_MODIFIED => {
+                return Err(Error::NotModified { path: req.path, metadata: Metadata::from(res.headers()) });
+            }
+            status => {
+                return Err(Error::Upstream {
+                    status_code: Some(status.as_u16()),
+                    message: res.text().await.unwrap_or_default(),
+                });
+            }
         }
 
-        let body = res.bytes().await.map_err(Error::Reqwest)?;
+        Ok(res)
+    }
+}
 
-        Ok(GetResponse {
-            content: body.to_vec(),
-            metadata: None,
-        })
+#[async_trait]
+impl<'a> Getter for WebFolderGetter<'a> {
+    async fn get(&self, req: GetRequest) -> Result<GetResponse, Error> {
+        client::read(self.send(req).await?, self.max_bytes).await
+    }
+
+    async fn get_stream(&self, req: GetRequest) -> Result<GetResponse<ByteStream>, Error> {
+        Ok(client::stream(self.send(req).await?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::time::{Duration, SystemTime};
+    use axum::http::HeaderMap;
+    use axum::routing::get;
+    use tokio::net::TcpListener;
+    use futures::TryStreamExt;
+    use crate::storage::types::{ByteRange, GetRequestOptions, Metadata};
+    use super::*;
+
+    async fn stand_in() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let router = axum::Router::new()
+            .route("/assets/cat.png", get(|| async {
+                (
+                    [
+                        ("Content-Type", "image/png"),
+                        ("Last-Modified", "Fri, 24 May 2013 00:00:00 GMT"),
+                        ("Cache-Control", "public, max-age=120"),
+                        ("ETag", "W/\"3-cat\""),
+                    ],
+                    vec![1, 2, 3],
+                )
+            }))
+            .route("/assets/range.png", get(|headers: HeaderMap| async move {
+                match headers.get(RANGE).and_then(|v| v.to_str().ok()) {
+                    Some("bytes=1-") => (StatusCode::PARTIAL_CONTENT, [("Content-Range", "bytes 1-2/3")], vec![2, 3]),
+                    _ => (StatusCode::OK, [("Accept-Ranges", "bytes")], vec![1, 2, 3]),
+                }
+            }));
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_get_metadata() {
+        let addr = stand_in().await;
+        let getter = WebFolderGetter::new(
+            Url::new(&format!("http://{}", addr)).unwrap(),
+            Some("/assets"),
+            &NetworkConfig::default(),
+            None,
+        ).unwrap();
+
+        let res = getter.get(GetRequest { path: "cat.png".to_string(), options: None })
+            .await
+            .unwrap();
+
+        assert_eq!(res.content, vec![1, 2, 3]);
+        assert_eq!(res.metadata, Some(Metadata {
+            content_type: Some("image/png".to_string()),
+            last_modified: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1369353600)),
+            cache_control: Some(Duration::from_secs(120)),
+            etag: Some("W/\"3-cat\"".to_string()),
+            content_length: Some(3),
+        }));
+    }
+
+    #[tokio::test]
+    async fn test_get_range() {
+        let addr = stand_in().await;
+        let getter = WebFolderGetter::new(
+            Url::new(&format!("http://{}/assets", addr)).unwrap(),
+            None,
+            &NetworkConfig::default(),
+            None,
+        ).unwrap();
+
+        let res = getter.get(GetRequest {
+            path: "range.png".to_string(),
+            options: Some(GetRequestOptions {
+                range: Some(ByteRange::new("bytes=1-")),
+                ..GetRequestOptions::default()
+            }),
+        }).await.unwrap();
+
+        assert_eq!(res.content, vec![2, 3]);
+        assert_eq!(res.content_range, Some("bytes 1-2/3".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_get_stream() {
+        let addr = stand_in().await;
+        let getter = WebFolderGetter::new(
+            Url::new(&format!("http://{}", addr)).unwrap(),
+            Some("/assets"),
+            &NetworkConfig::default(),
+            None,
+        ).unwrap();
+
+        let res = getter.get_stream(GetRequest { path: "cat.png".to_string(), options: None })
+            .await
+            .unwrap();
+
+        assert_eq!(res.metadata.unwrap().content_length, Some(3));
+        let chunks: Vec<_> = res.content.try_collect().await.unwrap();
+        assert_eq!(chunks.concat(), vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_get_not_found() {
+        let addr = stand_in().await;
+        let getter = WebFolderGetter::new(
+            Url::new(&format!("http://{}", addr)).unwrap(),
+            Some("/assets"),
+            &NetworkConfig::default(),
+            None,
+        ).unwrap();
+
+        let err = getter.get(GetRequest { path: "missing.png".to_string(), options: None })
+            .await
+            .err()
+            .unwrap();
+
+        assert!(matches!(err, Error::ObjectNotFound { ref path } if path == "missing.png"));
+        assert_eq!(err.status_code(), StatusCode::NOT_FOUND);
     }
 }
@@ -0,0 +1,66 @@
+/// Surrogate keys of an image: its path followed by each of the directories containing it,
+/// so that a CDN can purge every variant of one original, or everything under a prefix.
+pub fn keys(path: &str) -> Vec<String> {
+    let key = key(path);
+    let mut keys = vec![];
+
+    let mut prefix = key.as_str();
+    while !prefix.is_empty() {
+        keys.push(prefix.to_string());
+        prefix = prefix.rsplit_once('/').map_or("", |(parent, _)| parent);
+    }
+    keys
+}
+
+/// Whether purging `key` covers the image at `path`, that is `key` is one of its keys.
+pub fn covers(key: &str, path: &str) -> bool {
+    keys(path).iter().any(|k| k == key)
+}
+
+/// Normalizes a path or prefix into a key. Characters that cannot appear in a header
+/// value or that separate keys in `Surrogate-Key` and `Cache-Tag` headers are
+/// percent-encoded.
+pub fn key(path: &str) -> String {
+    let path = path.split('/').filter(|s| !s.is_empty()).collect::<Vec<_>>().join("/");
+
+    let mut key = String::with_capacity(path.len());
+    for c in path.chars() {
+        if c.is_ascii_graphic() && c != ',' && c != '%' {
+            key.push(c);
+            continue;
+        }
+        for b in c.encode_utf8(&mut [0; 4]).bytes() {
+            key += &format!("%{:02X}", b);
+        }
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys() {
+        let testcases = vec![
+            ("avatars/2024/a.jpg", vec!["avatars/2024/a.jpg", "avatars/2024", "avatars"]),
+            ("/a.jpg", vec!["a.jpg"]),
+            ("//avatars//a.jpg/", vec!["avatars/a.jpg", "avatars"]),
+            ("my photos/a,b.jpg", vec!["my%20photos/a%2Cb.jpg", "my%20photos"]),
+            ("café/100%.jpg", vec!["caf%C3%A9/100%25.jpg", "caf%C3%A9"]),
+            ("", vec![]),
+        ];
+
+        for (path, expected) in testcases {
+            assert_eq!(keys(path), expected, "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_covers() {
+        assert!(covers("avatars", "/avatars/2024/a.jpg"));
+        assert!(covers("avatars/2024/a.jpg", "avatars/2024/a.jpg"));
+        assert!(!covers("avatars/2024/a.jpg", "avatars/2024/a.jpg.webp"));
+        assert!(!covers("avatars/20", "avatars/2024/a.jpg"));
+    }
+}